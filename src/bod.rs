@@ -0,0 +1,322 @@
+use crate::hand::*;
+use crate::position::*;
+use crate::types::*;
+use anyhow::{anyhow, Result};
+
+// BOD board diagrams of KIF.
+// e.g.
+// 後手の持駒：飛　角　金二
+//   ９ ８ ７ ６ ５ ４ ３ ２ １
+// +---------------------------+
+// | ・ ・ ・ ・ ・ ・ ・v桂v香|一
+// ...
+// +---------------------------+
+// 先手の持駒：なし
+// 後手番
+// Black is to move without "後手番".
+// "残り全部" in a hand means all the pieces which are neither on the board nor in the other hand.
+
+const FILE_LINE: &str = "  ９ ８ ７ ６ ５ ４ ３ ２ １";
+const FRAME_LINE: &str = "+---------------------------+";
+const RANK_CHARS: [char; Rank::NUM] = ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+const EMPTY_CHAR: char = '・';
+
+const PIECE_CHARS: [(char, PieceType); 16] = [
+    ('歩', PieceType::PAWN),
+    ('香', PieceType::LANCE),
+    ('桂', PieceType::KNIGHT),
+    ('銀', PieceType::SILVER),
+    ('金', PieceType::GOLD),
+    ('角', PieceType::BISHOP),
+    ('飛', PieceType::ROOK),
+    ('玉', PieceType::KING),
+    ('と', PieceType::PRO_PAWN),
+    ('杏', PieceType::PRO_LANCE),
+    ('圭', PieceType::PRO_KNIGHT),
+    ('全', PieceType::PRO_SILVER),
+    ('馬', PieceType::HORSE),
+    ('龍', PieceType::DRAGON),
+    // The alternative characters for parsing.
+    ('王', PieceType::KING),
+    ('竜', PieceType::DRAGON),
+];
+
+// "一" to "十八". The index is the number - 1.
+const KANJI_NUMBERS: [&str; 18] = [
+    "一", "二", "三", "四", "五", "六", "七", "八", "九", "十", "十一", "十二", "十三", "十四", "十五", "十六", "十七", "十八",
+];
+
+fn piece_type_from_char(c: char) -> Option<PieceType> {
+    PIECE_CHARS.iter().find(|(x, _)| *x == c).map(|(_, pt)| *pt)
+}
+
+fn piece_type_to_char(pt: PieceType) -> char {
+    PIECE_CHARS.iter().find(|(_, x)| *x == pt).map(|(c, _)| *c).unwrap()
+}
+
+// "飛　角　金二　歩十八", "なし" or "残り全部"
+fn parse_hand(
+    s: &str,
+    c: Color,
+    hands: &mut [[u32; HAND_PIECE_TYPES.len()]; Color::NUM],
+    all_remaining: &mut Option<Color>,
+) -> Result<()> {
+    for token in s.split(['　', ' ']).filter(|token| !token.is_empty()) {
+        if token == "なし" {
+            continue;
+        }
+        if token == "残り全部" {
+            if all_remaining.is_some() {
+                return Err(anyhow!(r#""残り全部" twice. found "{}""#, s));
+            }
+            *all_remaining = Some(c);
+            continue;
+        }
+        let mut chars = token.chars();
+        let pt = chars
+            .next()
+            .and_then(piece_type_from_char)
+            .ok_or_else(|| anyhow!(r#"invalid hand piece "{}""#, token))?;
+        let index = HAND_PIECE_TYPES
+            .iter()
+            .position(|x| *x == pt)
+            .ok_or_else(|| anyhow!(r#"invalid hand piece "{}""#, token))?;
+        let num = match chars.as_str() {
+            "" => 1,
+            num => {
+                KANJI_NUMBERS
+                    .iter()
+                    .position(|x| *x == num)
+                    .ok_or_else(|| anyhow!(r#"invalid number of hand pieces "{}""#, token))?
+                    + 1
+            }
+        };
+        hands[c.0 as usize][index] += num as u32;
+    }
+    Ok(())
+}
+
+// "|v香v桂 ・ ・ ・ ・ ・v桂v香|一"
+fn parse_rank(line: &str) -> Result<[Piece; File::NUM]> {
+    let cells = line
+        .strip_prefix('|')
+        .and_then(|s| s.split('|').next())
+        .ok_or_else(|| anyhow!(r#"invalid rank "{}""#, line))?;
+    let chars = cells.chars().collect::<Vec<_>>();
+    if chars.len() != File::NUM * 2 {
+        return Err(anyhow!(r#"invalid number of squares in "{}""#, line));
+    }
+    let mut pieces = [Piece::EMPTY; File::NUM];
+    for (pc, cell) in pieces.iter_mut().zip(chars.chunks(2)) {
+        let c = match cell[0] {
+            'v' | 'V' => Color::WHITE,
+            ' ' | '^' => Color::BLACK,
+            _ => {
+                return Err(anyhow!(
+                    r#"invalid square "{}" in "{}""#,
+                    cell.iter().collect::<String>(),
+                    line
+                ))
+            }
+        };
+        if cell[1] == EMPTY_CHAR {
+            continue;
+        }
+        let pt = piece_type_from_char(cell[1])
+            .ok_or_else(|| anyhow!(r#"invalid piece "{}" in "{}""#, cell.iter().collect::<String>(), line))?;
+        *pc = Piece::new(c, pt);
+    }
+    Ok(pieces)
+}
+
+// Return true if the line is a part of a BOD.
+pub fn is_bod_line(line: &str) -> bool {
+    let line = line.trim();
+    line.contains("の持駒")
+        || line.starts_with('|')
+        || line.starts_with("+---")
+        || line.starts_with('９')
+        || ["先手番", "後手番", "上手番", "下手番"].contains(&line)
+}
+
+// The sfen of a BOD. The lines which aren't a part of the BOD are ignored.
+pub fn bod_to_sfen(bod: &str) -> Result<String> {
+    let mut ranks = vec![];
+    let mut hands = [[0; HAND_PIECE_TYPES.len()]; Color::NUM];
+    let mut all_remaining = None;
+    let mut side_to_move = Color::BLACK;
+    for line in bod.lines() {
+        let line = line.trim_end();
+        let trimmed = line.trim_start();
+        if let Some((key, value)) = trimmed.split_once('：').or_else(|| trimmed.split_once(':')) {
+            if key.ends_with("の持駒") {
+                // "上手" is white and "下手" is black in handicap games.
+                let c = if key.starts_with("後手") || key.starts_with("上手") {
+                    Color::WHITE
+                } else {
+                    Color::BLACK
+                };
+                parse_hand(value, c, &mut hands, &mut all_remaining)?;
+            }
+        } else if trimmed.starts_with('|') {
+            ranks.push(parse_rank(trimmed)?);
+        } else if trimmed == "後手番" || trimmed == "上手番" {
+            side_to_move = Color::WHITE;
+        } else if trimmed == "先手番" || trimmed == "下手番" {
+            side_to_move = Color::BLACK;
+        }
+    }
+    if ranks.len() != Rank::NUM {
+        return Err(anyhow!("invalid number of ranks. found {} ranks.", ranks.len()));
+    }
+    if let Some(c) = all_remaining {
+        for (index, pt) in HAND_PIECE_TYPES.iter().enumerate() {
+            let on_board = ranks
+                .iter()
+                .flatten()
+                .filter(|pc| **pc != Piece::EMPTY && !pc.is_king() && PieceType::new(**pc).to_demote_if_possible() == *pt)
+                .count();
+            let in_hands = (hands[0][index] + hands[1][index]) as usize;
+            hands[c.0 as usize][index] += max_piece_num(*pt).saturating_sub(on_board + in_hands) as u32;
+        }
+    }
+    let mut s = "".to_string();
+    for pieces in ranks.iter() {
+        if !s.is_empty() {
+            s += "/";
+        }
+        let mut empty_squares = 0;
+        for pc in pieces.iter() {
+            if *pc == Piece::EMPTY {
+                empty_squares += 1;
+            } else {
+                if empty_squares != 0 {
+                    s += &empty_squares.to_string();
+                    empty_squares = 0;
+                }
+                s += pc.to_usi_str();
+            }
+        }
+        if empty_squares != 0 {
+            s += &empty_squares.to_string();
+        }
+    }
+    s += if side_to_move == Color::BLACK { " b " } else { " w " };
+    let mut hand_str = "".to_string();
+    for c in [Color::BLACK, Color::WHITE].iter() {
+        for (index, pt) in HAND_PIECE_TYPES.iter().enumerate() {
+            let num = hands[c.0 as usize][index];
+            if num > 1 {
+                hand_str += &num.to_string();
+            }
+            if num != 0 {
+                hand_str += Piece::new(*c, *pt).to_usi_str();
+            }
+        }
+    }
+    s += if hand_str.is_empty() { "-" } else { &hand_str };
+    s += " 1";
+    Ok(s)
+}
+
+fn hand_to_bod_string(pos: &Position, c: Color) -> String {
+    let pieces = HAND_PIECE_TYPES
+        .iter()
+        .filter_map(|pt| match pos.hand(c).num(*pt) {
+            0 => None,
+            1 => Some(piece_type_to_char(*pt).to_string()),
+            num => Some(format!("{}{}", piece_type_to_char(*pt), KANJI_NUMBERS[num as usize - 1])),
+        })
+        .collect::<Vec<_>>();
+    if pieces.is_empty() {
+        "なし".to_string()
+    } else {
+        pieces.join("　")
+    }
+}
+
+pub fn position_to_bod_string(pos: &Position) -> String {
+    let mut s = format!("後手の持駒：{}\n", hand_to_bod_string(pos, Color::WHITE));
+    s += FILE_LINE;
+    s += "\n";
+    s += FRAME_LINE;
+    s += "\n";
+    for (rank, rank_char) in Rank::ALL_FROM_UPPER.iter().zip(RANK_CHARS.iter()) {
+        s += "|";
+        for file in File::ALL_FROM_LEFT.iter() {
+            let pc = pos.piece_on(Square::new(*file, *rank));
+            if pc == Piece::EMPTY {
+                s.push(' ');
+                s.push(EMPTY_CHAR);
+            } else {
+                s.push(if Color::new(pc) == Color::WHITE { 'v' } else { ' ' });
+                s.push(piece_type_to_char(PieceType::new(pc)));
+            }
+        }
+        s += "|";
+        s.push(*rank_char);
+        s += "\n";
+    }
+    s += FRAME_LINE;
+    s += "\n";
+    s += &format!("先手の持駒：{}\n", hand_to_bod_string(pos, Color::BLACK));
+    if pos.side_to_move() == Color::WHITE {
+        s += "後手番\n";
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfen::START_SFEN;
+
+    #[test]
+    fn test_bod() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let bod = "後手の持駒：飛　角　金二　銀　桂三　香三　歩十七\n\
+                           \x20 ９ ８ ７ ６ ５ ４ ３ ２ １\n\
+                           +---------------------------+\n\
+                           | ・ ・ ・ ・ ・ ・ ・v桂v香|一\n\
+                           | ・ ・ ・ ・ ・ ・v玉 ・ ・|二\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|三\n\
+                           | ・ ・ ・ ・ ・ ・ ・ 龍 ・|四\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|五\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|六\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|七\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|八\n\
+                           | ・ ・ ・ ・ 玉 ・ ・ ・ 全|九\n\
+                           +---------------------------+\n\
+                           先手の持駒：金　銀二　歩\n";
+                let sfen = bod_to_sfen(bod).unwrap();
+                assert_eq!(sfen, "7nl/6k2/9/7+R1/9/9/9/9/4K3+S b G2SPrb2gs3n3l17p 1");
+                let pos = Position::new_from_sfen(&sfen).unwrap();
+                assert_eq!(position_to_bod_string(&pos), bod);
+
+                for sfen in [
+                    START_SFEN,
+                    "l4S2l/4g1gs1/5p1p1/pr2N1pkp/4Gn3/PP3PPPP/2GPP4/1K7/L3r+s2L w BS2N5Pb 1",
+                ] {
+                    let pos = Position::new_from_sfen(sfen).unwrap();
+                    assert_eq!(bod_to_sfen(&position_to_bod_string(&pos)).unwrap(), sfen);
+                }
+
+                assert!(bod_to_sfen("先手の持駒：なし\n").is_err());
+                assert!(bod_to_sfen(&bod.replace("v桂v香|一", "v桂v香 ・|一")).is_err());
+                assert!(bod_to_sfen(&bod.replace("歩十七", "歩十九")).is_err());
+                assert!(bod_to_sfen(&bod.replace("v桂v香|一", "v桂v猫|一")).is_err());
+
+                let tsume = bod.replace("飛　角　金二　銀　桂三　香三　歩十七", "残り全部");
+                assert_eq!(
+                    bod_to_sfen(&tsume).unwrap(),
+                    "7nl/6k2/9/7+R1/9/9/9/9/4K3+S b G2SPr2b3gs3n3l17p 1"
+                );
+                assert!(bod_to_sfen(&tsume.replace("金　銀二　歩", "残り全部")).is_err());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
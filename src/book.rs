@@ -1,17 +1,154 @@
+#[cfg(feature = "kppt")]
+use crate::evaluate::kppt::*;
+use crate::file_to_vec::*;
+use crate::huffman_code::*;
 use crate::movetypes::*;
+use crate::piecevalue::*;
 use crate::position::*;
+use crate::search::*;
+use crate::thread::*;
+use crate::tt::*;
 use crate::types::*;
-use anyhow::Result;
+use crate::usi::*;
+use crate::usioption::*;
+use anyhow::{anyhow, Context, Result};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::io::Write;
 
-#[derive(Serialize, Deserialize, Debug, PartialOrd, Ord, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq)]
 struct Info {
     value: Value,
     win: u64,
     lose: u64,
+    // The results of the games aren't known (e.g. imported from YaneuraOu db). win + lose is the number of games.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    count_only: bool,
 }
 
+impl Info {
+    fn merge(&mut self, other: Info) {
+        // The value of the entry with more games is more reliable.
+        if self.win + self.lose < other.win + other.lose {
+            self.value = other.value;
+        }
+        self.win += other.win;
+        self.lose += other.lose;
+        self.count_only |= other.count_only;
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BookStats {
+    pub positions: usize,
+    pub moves: usize,
+    pub merged_positions: usize, // positions stored under more than one key (e.g. different game plies).
+    pub collided_moves: usize,   // moves found in more than one of the merged entries.
+}
+
+impl std::fmt::Display for BookStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "positions {} moves {} merged_positions {} collided_moves {}",
+            self.positions, self.moves, self.merged_positions, self.collided_moves
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BookPolicy {
+    BestValue, // Only the moves of the best value.
+    WinCount,  // Weighted by the number of wins.
+    WinRate,   // Weighted by the square of the win rate.
+    Uniform,
+}
+
+impl BookPolicy {
+    pub fn new_from_str(s: &str) -> Option<BookPolicy> {
+        match s {
+            "BestValue" => Some(BookPolicy::BestValue),
+            "WinCount" => Some(BookPolicy::WinCount),
+            "WinRate" => Some(BookPolicy::WinRate),
+            "Uniform" => Some(BookPolicy::Uniform),
+            _ => None,
+        }
+    }
+}
+
+pub struct BookProbeSettings {
+    pub policy: BookPolicy,
+    pub narrow_margin: Option<Value>, // If this is some, the moves worse than the best value by more than this are ignored.
+    pub min_games: u64,               // The moves which have fewer games than this are ignored.
+}
+
+impl BookProbeSettings {
+    pub fn new(usi_options: &UsiOptions) -> BookProbeSettings {
+        BookProbeSettings {
+            policy: BookPolicy::new_from_str(&usi_options.get_string(UsiOptions::BOOK_POLICY)).unwrap(),
+            narrow_margin: if usi_options.get_bool(UsiOptions::NARROW_BOOK) {
+                // The option is centipawn.
+                Some(Value(
+                    usi_options.get_i64(UsiOptions::BOOK_NARROW_MARGIN) as i32 * PAWN_VALUE / 100,
+                ))
+            } else {
+                None
+            },
+            min_games: usi_options.get_i64(UsiOptions::BOOK_MIN_GAMES) as u64,
+        }
+    }
+}
+
+impl Default for BookProbeSettings {
+    fn default() -> BookProbeSettings {
+        BookProbeSettings {
+            policy: BookPolicy::WinRate,
+            narrow_margin: None,
+            min_games: 0,
+        }
+    }
+}
+
+// Choose one of the book moves of a position by the settings. This is shared by all the book formats.
+fn select_book_move(candidates: Vec<(Move, Info)>, settings: &BookProbeSettings, rng: &mut ThreadRng) -> Option<(Move, Value)> {
+    let candidates = candidates
+        .into_iter()
+        .filter(|(_, info)| info.win + info.lose >= settings.min_games)
+        .collect::<Vec<_>>();
+    let best_value = candidates.iter().map(|(_, info)| info.value).max()?;
+    let candidates = match settings.narrow_margin {
+        Some(margin) => candidates
+            .into_iter()
+            .filter(|(_, info)| info.value >= best_value - margin)
+            .collect::<Vec<_>>(),
+        None => candidates,
+    };
+    let weight = |info: &Info| match settings.policy {
+        BookPolicy::BestValue => {
+            if info.value == best_value {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        // The number of games is the only weight of the moves whose results aren't known.
+        BookPolicy::WinCount | BookPolicy::WinRate if info.count_only => (info.win + info.lose) as f64,
+        BookPolicy::WinCount => info.win as f64,
+        BookPolicy::WinRate => {
+            let win_rate = info.win as f64 / (info.win + info.lose) as f64;
+            win_rate * win_rate
+        }
+        BookPolicy::Uniform => 1.0,
+    };
+    let (m, info) = match rand::distributions::WeightedIndex::new(candidates.iter().map(|(_, info)| weight(info))) {
+        Ok(dist) => candidates[dist.sample(rng)],
+        // All weights are zero (e.g. no wins). Choose from all candidates.
+        Err(_) => *candidates.choose(rng)?,
+    };
+    Some((m, info.value))
+}
+
+// The key is sfen without game ply, so the same position reached at any ply hits the same entry.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Book(std::collections::BTreeMap<String, std::collections::BTreeMap<UsiMove, Info>>);
 
@@ -20,36 +157,810 @@ impl Book {
     pub fn new() -> Book {
         Book(std::collections::BTreeMap::new())
     }
+    // board, side to move and hands of sfen. the game ply is dropped.
+    fn key_from_sfen(sfen: &str) -> String {
+        sfen.split_whitespace().take(3).collect::<Vec<_>>().join(" ")
+    }
+    fn key(pos: &Position) -> String {
+        Self::key_from_sfen(&pos.to_sfen())
+    }
     #[allow(dead_code)]
     fn insert(&mut self, sfen: String, mv: Move, info: Info) {
-        let set = self.0.entry(sfen).or_default();
+        let set = self.0.entry(Self::key_from_sfen(&sfen)).or_default();
         set.insert(mv.to_usi(), info);
     }
+    fn get_mut(&mut self, sfen: &str, m: Move) -> Option<&mut Info> {
+        self.0.get_mut(&Self::key_from_sfen(sfen))?.get_mut(&m.to_usi())
+    }
     #[allow(dead_code)]
     pub fn probe(&self, pos: &Position, rng: &mut ThreadRng) -> Option<Move> {
-        let sfen = pos.to_sfen();
-        let candidates = self.0.get(&sfen)?;
-        let move_and_weights = candidates
+        self.probe_with_settings(pos, &BookProbeSettings::default(), rng)
+            .map(|(m, _)| m)
+    }
+    // Return the book move and its value.
+    pub fn probe_with_settings(
+        &self,
+        pos: &Position,
+        settings: &BookProbeSettings,
+        rng: &mut ThreadRng,
+    ) -> Option<(Move, Value)> {
+        let candidates = self
+            .0
+            .get(&Self::key(pos))?
             .iter()
-            .map(|(usi_move, info)| {
-                let win_rate = info.win as f64 / (info.win + info.lose) as f64;
-                let weight = win_rate * win_rate;
-                (usi_move, weight)
-            })
+            .filter_map(|(usi_move, info)| Some((Move::new_from_usi(usi_move, pos)?, *info)))
             .collect::<Vec<_>>();
-        let dist = rand::distributions::WeightedIndex::new(move_and_weights.iter().map(|x| x.1)).unwrap();
-        let usi_move = move_and_weights[dist.sample(rng)].0;
-        Move::new_from_usi(usi_move, pos)
+        select_book_move(candidates, settings, rng)
+    }
+    // Rewrite the keys of the book which may have the keys with game ply (old format).
+    // The entries of the same position are merged.
+    fn migrate(self) -> (Book, BookStats) {
+        use std::collections::btree_map::Entry;
+        let mut book = Book::new();
+        let mut collided_moves = 0;
+        let mut merged_keys = std::collections::BTreeSet::new();
+        for (sfen, candidates) in self.0 {
+            let key = Self::key_from_sfen(&sfen);
+            match book.0.entry(key) {
+                Entry::Vacant(entry) => {
+                    entry.insert(candidates);
+                }
+                Entry::Occupied(entry) => {
+                    merged_keys.insert(entry.key().clone());
+                    let set = entry.into_mut();
+                    for (usi_move, info) in candidates {
+                        match set.entry(usi_move) {
+                            Entry::Vacant(entry) => {
+                                entry.insert(info);
+                            }
+                            Entry::Occupied(mut entry) => {
+                                collided_moves += 1;
+                                entry.get_mut().merge(info);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let stats = BookStats {
+            merged_positions: merged_keys.len(),
+            collided_moves,
+            ..book.stats()
+        };
+        (book, stats)
+    }
+    // The positions reached from root by the book moves which aren't in the book yet.
+    // Return the sfens of them.
+    fn leaves(&self, root: &Position, max_ply: i32) -> Vec<String> {
+        fn leaves_impl(
+            book: &Book,
+            pos: &mut Position,
+            max_ply: i32,
+            visited: &mut std::collections::HashSet<String>,
+            leaves: &mut Vec<String>,
+        ) {
+            let key = Book::key(pos);
+            if !visited.insert(key.clone()) {
+                return;
+            }
+            match book.0.get(&key) {
+                Some(candidates) => {
+                    if pos.ply() >= max_ply {
+                        return;
+                    }
+                    for usi_move in candidates.keys() {
+                        if let Some(m) = Move::new_from_usi(usi_move, pos) {
+                            let gives_check = pos.gives_check(m);
+                            pos.do_move(m, gives_check);
+                            leaves_impl(book, pos, max_ply, visited, leaves);
+                            pos.undo_move(m);
+                        }
+                    }
+                }
+                None => leaves.push(pos.to_sfen()),
+            }
+        }
+        let mut pos = Position::new_from_position(root, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+        let mut visited = std::collections::HashSet::new();
+        let mut leaves = vec![];
+        leaves_impl(self, &mut pos, max_ply, &mut visited, &mut leaves);
+        leaves
+    }
+    // Set the value of each book move to the minimax value of the position after the move, from root.
+    fn back_propagate(&mut self, root: &Position) {
+        fn back_propagate_impl(
+            book: &mut Book,
+            pos: &mut Position,
+            visiting: &mut std::collections::HashSet<String>,
+            done: &mut std::collections::HashMap<String, Value>,
+        ) -> Option<Value> {
+            let key = Book::key(pos);
+            if let Some(&value) = done.get(&key) {
+                return Some(value);
+            }
+            if !book.0.contains_key(&key) || visiting.contains(&key) {
+                // Not in the book, or repetition. Use the stored value.
+                return None;
+            }
+            visiting.insert(key.clone());
+            let moves = book.0[&key]
+                .keys()
+                .filter_map(|usi_move| Move::new_from_usi(usi_move, pos))
+                .collect::<Vec<_>>();
+            for m in moves {
+                let gives_check = pos.gives_check(m);
+                pos.do_move(m, gives_check);
+                let child_value = back_propagate_impl(book, pos, visiting, done);
+                pos.undo_move(m);
+                if let Some(child_value) = child_value {
+                    book.0.get_mut(&key).unwrap().get_mut(&m.to_usi()).unwrap().value = -child_value;
+                }
+            }
+            visiting.remove(&key);
+            let value = book.0[&key].values().map(|info| info.value).max()?;
+            done.insert(key, value);
+            Some(value)
+        }
+        let mut pos = Position::new_from_position(root, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+        back_propagate_impl(
+            self,
+            &mut pos,
+            &mut std::collections::HashSet::new(),
+            &mut std::collections::HashMap::new(),
+        );
+    }
+    pub fn stats(&self) -> BookStats {
+        BookStats {
+            positions: self.0.len(),
+            moves: self.0.values().map(|set| set.len()).sum(),
+            ..Default::default()
+        }
+    }
+    pub fn from_file_with_stats<P>(path: P) -> Result<(Book, BookStats)>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        use std::io::BufRead;
+        let file = std::fs::File::open(path)?;
+        let mut reader = std::io::BufReader::new(file);
+        let book = if reader.fill_buf()?.starts_with(Self::YANEURAOU_DB_HEADER.as_bytes()) {
+            Self::from_yaneuraou_db_reader(reader)?
+        } else {
+            serde_json::from_reader(reader)?
+        };
+        Ok(book.migrate())
     }
     pub fn from_file<P>(path: P) -> Result<Book>
     where
         P: AsRef<std::path::Path>,
     {
+        Ok(Self::from_file_with_stats(path)?.0)
+    }
+    pub fn to_file<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let file = std::fs::File::create(path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+    const YANEURAOU_DB_HEADER: &'static str = "#YANEURAOU-DB2016 1.00";
+    pub fn is_yaneuraou_db_file<P>(path: P) -> Result<bool>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        use std::io::BufRead;
         let file = std::fs::File::open(path)?;
-        let reader = std::io::BufReader::new(file);
-        let book = serde_json::from_reader(reader)?;
+        let mut reader = std::io::BufReader::new(file);
+        Ok(reader.fill_buf()?.starts_with(Self::YANEURAOU_DB_HEADER.as_bytes()))
+    }
+    // The values of YaneuraOu db are in centipawns.
+    fn value_from_centipawns(cp: i32) -> Value {
+        Value((f64::from(cp) * f64::from(PAWN_VALUE) / 100.0).round() as i32)
+    }
+    fn value_to_centipawns(v: Value) -> i32 {
+        (f64::from(v.0) * 100.0 / f64::from(PAWN_VALUE)).round() as i32
+    }
+    // YaneuraOu standard book format. "sfen <sfen>" line is followed by "<move> <ponder> <value> <depth> <count>" lines.
+    // The count is the number of games. The results aren't recorded, so the count is kept as win with count_only.
+    // Reading and writing the db loses the wins and losses. e.g. 6 wins and 4 losses come back as 10 games.
+    // The keys keep the game ply of the sfen lines. They are merged by migrate().
+    fn from_yaneuraou_db_reader<R>(reader: R) -> Result<Book>
+    where
+        R: std::io::BufRead,
+    {
+        let mut book = Book::new();
+        let mut current: Option<(String, Position)> = None;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            if let Some(sfen) = line.strip_prefix("sfen ") {
+                let sfen = sfen.trim();
+                let pos = Position::new_from_sfen(sfen)
+                    .map_err(|e| anyhow!(r#"cannot parse "{}" as sfen at line {}. {}"#, sfen, i + 1, e))?;
+                current = Some((sfen.to_string(), pos));
+                continue;
+            }
+            let (sfen, pos) = current
+                .as_ref()
+                .ok_or_else(|| anyhow!(r#"the move "{}" before sfen at line {}."#, line, i + 1))?;
+            let v: Vec<&str> = line.split_whitespace().collect();
+            let m = Move::new_from_usi_str(v[0], pos).ok_or_else(|| anyhow!(r#"illegal move "{}" at line {}."#, v[0], i + 1))?;
+            let value = match v.get(2) {
+                Some(s) => s
+                    .parse::<i32>()
+                    .with_context(|| anyhow!(r#"cannot parse "{}" as value at line {}."#, s, i + 1))?,
+                None => 0,
+            };
+            let count = match v.get(4) {
+                Some(s) => s
+                    .parse::<u64>()
+                    .with_context(|| anyhow!(r#"cannot parse "{}" as count at line {}."#, s, i + 1))?,
+                None => 1,
+            };
+            book.0.entry(sfen.clone()).or_default().insert(
+                m.to_usi(),
+                Info {
+                    value: Self::value_from_centipawns(value),
+                    win: count,
+                    lose: 0,
+                    count_only: true,
+                },
+            );
+        }
         Ok(book)
     }
+    // The game ply of each position is the shortest one from the start position by the book moves.
+    // The positions which can't be reached get ply 1.
+    fn plies_from_start_position(&self) -> std::collections::HashMap<String, i32> {
+        let mut plies = std::collections::HashMap::new();
+        let start = Position::new();
+        plies.insert(Self::key(&start), start.ply());
+        let mut queue = std::collections::VecDeque::from(vec![start.to_sfen()]);
+        while let Some(sfen) = queue.pop_front() {
+            let mut pos = Position::new_from_sfen(&sfen).unwrap();
+            let candidates = match self.0.get(&Self::key(&pos)) {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+            for usi_move in candidates.keys() {
+                if let Some(m) = Move::new_from_usi(usi_move, &pos) {
+                    let gives_check = pos.gives_check(m);
+                    pos.do_move(m, gives_check);
+                    let key = Self::key(&pos);
+                    if self.0.contains_key(&key) && !plies.contains_key(&key) {
+                        plies.insert(key, pos.ply());
+                        queue.push_back(pos.to_sfen());
+                    }
+                    pos.undo_move(m);
+                }
+            }
+        }
+        plies
+    }
+    // The count is the number of games (wins + losses), the depth is 0, and the ponder is the best move of the next position in the book.
+    pub fn to_yaneuraou_db_file<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let plies = self.plies_from_start_position();
+        let file = std::fs::File::create(path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        writeln!(writer, "{}", Self::YANEURAOU_DB_HEADER)?;
+        for (key, candidates) in self.0.iter() {
+            let ply = plies.get(key).copied().unwrap_or(1);
+            let mut pos = Position::new_from_sfen(&format!("{} {}", key, ply))
+                .map_err(|e| anyhow!(r#"cannot parse "{}" as sfen. {}"#, key, e))?;
+            let mut lines = vec![];
+            for (usi_move, info) in candidates.iter() {
+                let m = Move::new_from_usi(usi_move, &pos).ok_or_else(|| anyhow!(r#"illegal book move in "{}""#, key))?;
+                let gives_check = pos.gives_check(m);
+                pos.do_move(m, gives_check);
+                let ponder = self
+                    .0
+                    .get(&Self::key(&pos))
+                    .and_then(|next| next.iter().max_by_key(|(_, info)| (info.value, info.win)))
+                    .and_then(|(usi_move, _)| Move::new_from_usi(usi_move, &pos))
+                    .map_or("none".to_string(), |m| m.to_usi_string());
+                pos.undo_move(m);
+                lines.push((
+                    info,
+                    format!(
+                        "{} {} {} 0 {}",
+                        m.to_usi_string(),
+                        ponder,
+                        Self::value_to_centipawns(info.value),
+                        info.win + info.lose
+                    ),
+                ));
+            }
+            // YaneuraOu sorts the moves in descending order of value.
+            lines.sort_by_key(|(info, _)| std::cmp::Reverse((info.value, info.win + info.lose)));
+            writeln!(writer, "sfen {} {}", key, ply)?;
+            for (_, line) in lines {
+                writeln!(writer, "{}", line)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// An entry of the binary book.
+// The binary book file is the header entry followed by the entries sorted by key and move.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BinaryBookEntry {
+    key: u64, // Position::key()
+    mv16: u16,
+    value: i16,
+    win: u32,
+    lose: u32,
+    flags: u32,
+}
+
+impl BinaryBookEntry {
+    const COUNT_ONLY: u32 = 1; // Info::count_only
+
+    fn info(&self) -> Info {
+        Info {
+            value: Value(i32::from(self.value)),
+            win: u64::from(self.win),
+            lose: u64::from(self.lose),
+            count_only: self.flags & Self::COUNT_ONLY != 0,
+        }
+    }
+}
+
+// The book keyed by the hash key of the position. It can be loaded without parsing and probed by binary search.
+// The positions can't be restored from the hash keys, so converting to the json book needs the start position.
+pub struct BinaryBook(Vec<BinaryBookEntry>);
+
+impl BinaryBook {
+    const MAGIC: u64 = u64::from_le_bytes(*b"APERYBK1");
+
+    fn header() -> BinaryBookEntry {
+        BinaryBookEntry {
+            key: Self::MAGIC,
+            mv16: 0,
+            value: 0,
+            win: 0,
+            lose: 0,
+            flags: 0,
+        }
+    }
+    pub fn is_binary_book_file<P>(path: P) -> Result<bool>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        use std::io::Read;
+        let mut file = std::fs::File::open(path)?;
+        let mut buf = [0_u8; 8];
+        match file.read_exact(&mut buf) {
+            Ok(()) => Ok(u64::from_le_bytes(buf) == Self::MAGIC),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+    pub fn from_book(book: &Book) -> Result<BinaryBook> {
+        let mut entries = vec![];
+        for (key, candidates) in book.0.iter() {
+            // The game ply isn't used for the hash key.
+            let pos = Position::new_from_sfen(&format!("{} 1", key))
+                .map_err(|e| anyhow!(r#"cannot parse "{}" as sfen. {}"#, key, e))?;
+            for (usi_move, info) in candidates.iter() {
+                let m = Move::new_from_usi(usi_move, &pos).ok_or_else(|| anyhow!(r#"illegal book move in "{}""#, key))?;
+                entries.push(BinaryBookEntry {
+                    key: pos.key().0,
+                    mv16: m.to_u16(),
+                    value: info.value.0.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16,
+                    win: u32::try_from(info.win).unwrap_or(u32::MAX),
+                    lose: u32::try_from(info.lose).unwrap_or(u32::MAX),
+                    flags: if info.count_only { BinaryBookEntry::COUNT_ONLY } else { 0 },
+                });
+            }
+        }
+        entries.sort_unstable_by_key(|e| (e.key, e.mv16));
+        // The hash keys of different positions may collide. Keep the first one.
+        entries.dedup_by_key(|e| (e.key, e.mv16));
+        Ok(BinaryBook(entries))
+    }
+    // Return the json book of the positions reached from root by the book moves,
+    // and the number of the entries which aren't reached.
+    pub fn to_book(&self, root: &Position) -> (Book, usize) {
+        fn to_book_impl(
+            binary_book: &BinaryBook,
+            pos: &mut Position,
+            visited: &mut std::collections::HashSet<u64>,
+            book: &mut Book,
+            converted: &mut usize,
+        ) {
+            if !visited.insert(pos.key().0) {
+                return;
+            }
+            let entries = binary_book.entries(pos.key());
+            for e in entries {
+                if let Some(m) = Move::new_from_u16(e.mv16, pos) {
+                    book.insert(pos.to_sfen(), m, e.info());
+                    *converted += 1;
+                    let gives_check = pos.gives_check(m);
+                    pos.do_move(m, gives_check);
+                    to_book_impl(binary_book, pos, visited, book, converted);
+                    pos.undo_move(m);
+                }
+            }
+        }
+        let mut pos = Position::new_from_position(root, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+        let mut book = Book::new();
+        let mut converted = 0;
+        to_book_impl(
+            self,
+            &mut pos,
+            &mut std::collections::HashSet::new(),
+            &mut book,
+            &mut converted,
+        );
+        (book, self.0.len() - converted)
+    }
+    fn get_mut(&mut self, sfen: &str, m: Move) -> Option<&mut BinaryBookEntry> {
+        let key = Position::new_from_sfen(sfen).ok()?.key().0;
+        let mv16 = m.to_u16();
+        let i = self.0.binary_search_by_key(&(key, mv16), |e| (e.key, e.mv16)).ok()?;
+        Some(&mut self.0[i])
+    }
+    fn entries(&self, key: Key) -> &[BinaryBookEntry] {
+        let begin = self.0.partition_point(|e| e.key < key.0);
+        let end = begin + self.0[begin..].partition_point(|e| e.key == key.0);
+        &self.0[begin..end]
+    }
+    pub fn probe_with_settings(
+        &self,
+        pos: &Position,
+        settings: &BookProbeSettings,
+        rng: &mut ThreadRng,
+    ) -> Option<(Move, Value)> {
+        let candidates = self
+            .entries(pos.key())
+            .iter()
+            .filter_map(|e| Some((Move::new_from_u16(e.mv16, pos)?, e.info())))
+            .collect::<Vec<_>>();
+        select_book_move(candidates, settings, rng)
+    }
+    pub fn stats(&self) -> BookStats {
+        BookStats {
+            positions: self
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(i, e)| *i == 0 || self.0[i - 1].key != e.key)
+                .count(),
+            moves: self.0.len(),
+            ..Default::default()
+        }
+    }
+    pub fn from_file<P>(path: P) -> Result<BinaryBook>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let mut entries: Vec<BinaryBookEntry> = file_to_vec(path.as_ref().to_string_lossy().into_owned())?;
+        if entries.first() != Some(&Self::header()) {
+            return Err(anyhow!("invalid binary book header."));
+        }
+        entries.remove(0);
+        if !entries.windows(2).all(|w| (w[0].key, w[0].mv16) < (w[1].key, w[1].mv16)) {
+            return Err(anyhow!("binary book entries aren't sorted."));
+        }
+        Ok(BinaryBook(entries))
+    }
+    pub fn to_file<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let file = std::fs::File::create(path)?;
+        let mut writer = std::io::BufWriter::new(file);
+        writer.write_all(as_u8_slice(&[Self::header()]))?;
+        writer.write_all(as_u8_slice(&self.0))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// The book of Book_File. The format is detected from the head of the file.
+pub enum AnyBook {
+    Json(Book),
+    YaneuraOuDb(Book),
+    Binary(BinaryBook),
+}
+
+impl AnyBook {
+    pub fn from_file<P>(path: P) -> Result<AnyBook>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        if BinaryBook::is_binary_book_file(&path)? {
+            Ok(AnyBook::Binary(BinaryBook::from_file(path)?))
+        } else if Book::is_yaneuraou_db_file(&path)? {
+            Ok(AnyBook::YaneuraOuDb(Book::from_file(path)?))
+        } else {
+            Ok(AnyBook::Json(Book::from_file(path)?))
+        }
+    }
+    // Write the book in the format it was read.
+    pub fn to_file<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        match self {
+            AnyBook::Json(book) => book.to_file(path),
+            AnyBook::YaneuraOuDb(book) => book.to_yaneuraou_db_file(path),
+            AnyBook::Binary(book) => book.to_file(path),
+        }
+    }
+    pub fn probe_with_settings(
+        &self,
+        pos: &Position,
+        settings: &BookProbeSettings,
+        rng: &mut ThreadRng,
+    ) -> Option<(Move, Value)> {
+        match self {
+            AnyBook::Json(book) | AnyBook::YaneuraOuDb(book) => book.probe_with_settings(pos, settings, rng),
+            AnyBook::Binary(book) => book.probe_with_settings(pos, settings, rng),
+        }
+    }
+    // Count the result of the game for the book move played at sfen.
+    // Return false if the move isn't in the book or its results aren't known (count_only).
+    // YaneuraOu db can't keep the results, so learning it is an error rather than losing the losses on saving.
+    pub fn learn(&mut self, sfen: &str, m: Move, win: bool) -> Result<bool> {
+        let count = |win_count: &mut u64, lose_count: &mut u64| {
+            if win {
+                *win_count += 1;
+            } else {
+                *lose_count += 1;
+            }
+        };
+        match self {
+            AnyBook::Json(book) => match book.get_mut(sfen, m) {
+                Some(info) if !info.count_only => {
+                    count(&mut info.win, &mut info.lose);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+            AnyBook::YaneuraOuDb(_) => Err(anyhow!("YaneuraOu db book can't learn. It doesn't keep the wins and losses.")),
+            AnyBook::Binary(book) => match book.get_mut(sfen, m) {
+                Some(e) if e.flags & BinaryBookEntry::COUNT_ONLY == 0 => {
+                    let (mut win_count, mut lose_count) = (u64::from(e.win), u64::from(e.lose));
+                    count(&mut win_count, &mut lose_count);
+                    e.win = u32::try_from(win_count).unwrap_or(u32::MAX);
+                    e.lose = u32::try_from(lose_count).unwrap_or(u32::MAX);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+        }
+    }
+    pub fn stats(&self) -> BookStats {
+        match self {
+            AnyBook::Json(book) | AnyBook::YaneuraOuDb(book) => book.stats(),
+            AnyBook::Binary(book) => book.stats(),
+        }
+    }
+}
+
+// Make a book from game records.
+pub struct BookMaker {
+    max_ply: i32,
+    // The number of times the move was played, and the results of the games.
+    entries: std::collections::BTreeMap<String, std::collections::BTreeMap<UsiMove, (u64, Info)>>,
+}
+
+impl BookMaker {
+    pub fn new(max_ply: i32) -> BookMaker {
+        BookMaker {
+            max_ply,
+            entries: std::collections::BTreeMap::new(),
+        }
+    }
+    // The moves must be legal from start.
+    // If result is None (e.g. the game was interrupted), the moves are only counted.
+    pub fn add_game(&mut self, start: &Position, moves: &[Move], result: Option<GameResult>) {
+        let mut pos = Position::new_from_position(start, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+        for &m in moves {
+            if pos.ply() > self.max_ply {
+                break;
+            }
+            let (count, info) = self.entries.entry(Book::key(&pos)).or_default().entry(m.to_usi()).or_insert((
+                0,
+                Info {
+                    value: Value::ZERO,
+                    win: 0,
+                    lose: 0,
+                    count_only: false,
+                },
+            ));
+            *count += 1;
+            match (result, pos.side_to_move()) {
+                (Some(GameResult::BlackWin), Color::BLACK) | (Some(GameResult::WhiteWin), Color::WHITE) => info.win += 1,
+                (Some(GameResult::BlackWin), _) | (Some(GameResult::WhiteWin), _) => info.lose += 1,
+                (Some(GameResult::Draw), _) | (None, _) => {}
+            }
+            let gives_check = pos.gives_check(m);
+            pos.do_move(m, gives_check);
+        }
+    }
+    // The moves played fewer than min_frequency times are dropped.
+    pub fn into_book(self, min_frequency: u64) -> Book {
+        let mut book = Book::new();
+        for (key, candidates) in self.entries {
+            let candidates = candidates
+                .into_iter()
+                .filter(|(_, (count, _))| *count >= min_frequency)
+                .map(|(usi_move, (_, info))| (usi_move, info))
+                .collect::<std::collections::BTreeMap<_, _>>();
+            if !candidates.is_empty() {
+                book.0.insert(key, candidates);
+            }
+        }
+        book
+    }
+}
+
+// The moves and values of the MultiPV root moves to add to the book.
+// The search stopped by search_nodes may leave the moves which aren't searched in the last iteration.
+// They have the values of the previous iteration like the "info" output, and the moves never searched are skipped.
+fn searched_book_moves(root_moves: &[RootMove]) -> Vec<(Move, Value)> {
+    root_moves
+        .iter()
+        .filter_map(|rm| {
+            let value = if rm.score != -Value::INFINITE {
+                rm.score
+            } else {
+                rm.previous_score
+            };
+            if Some(rm.pv[0]).is_normal_move() && value != -Value::INFINITE {
+                Some((rm.pv[0], value))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Search the leaves of the book from the start position and add the MultiPV moves of them to the book.
+// The values are back-propagated to the root. The book is written periodically, so it can be resumed.
+pub fn think_book(args: &[&str]) -> Result<()> {
+    if args.len() != 6 {
+        const S: &str = concat!(
+            "invalid think_book command.\n",
+            "expected:\n",
+            r#"think_book <book_file_path> <max_ply> <search_depth> <search_nodes> <multi_pv> <flush_interval>"#,
+            "\n",
+            "search_nodes 0 means no limit of nodes.",
+        );
+        return Err(anyhow!(S));
+    }
+    let book_file_path = args[0];
+    let max_ply = args[1]
+        .parse::<i32>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as max_ply."#, args[1]))?;
+    let search_depth = args[2]
+        .parse::<u32>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as search_depth."#, args[2]))?;
+    let search_nodes = args[3]
+        .parse::<u64>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as search_nodes."#, args[3]))?;
+    let multi_pv = args[4]
+        .parse::<usize>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as multi_pv."#, args[4]))?;
+    let flush_interval = args[5]
+        .parse::<usize>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as flush_interval."#, args[5]))?;
+    let mut book = if std::path::Path::new(book_file_path).exists() {
+        Book::from_file(book_file_path).map_err(|e| anyhow!("{}: {}", e, book_file_path))?
+    } else {
+        Book::new()
+    };
+
+    let mut thread_pool = ThreadPool::new();
+    let mut tt = TranspositionTable::new();
+    #[cfg(feature = "kppt")]
+    let mut ehash = EvalHash::new();
+    let mut reductions = Reductions::new();
+    thread_pool.set(
+        1,
+        &mut tt,
+        #[cfg(feature = "kppt")]
+        &mut ehash,
+        &mut reductions,
+    );
+    let mut is_ready = false;
+    let usi_options = {
+        let mut u = UsiOptions::new();
+        let multi_pv = multi_pv.to_string();
+        [
+            (UsiOptions::MULTI_PV, multi_pv.as_str()),
+            (UsiOptions::THREADS, "1"),
+            (UsiOptions::USI_HASH, "1024"),
+            #[cfg(feature = "kppt")]
+            (UsiOptions::EVAL_HASH, "256"),
+            (UsiOptions::BOOK_ENABLE, "false"),
+        ]
+        .iter()
+        .for_each(|(name, value)| {
+            setoption(
+                &["name", name, "value", value],
+                &mut u,
+                &mut thread_pool,
+                &mut tt,
+                #[cfg(feature = "kppt")]
+                &mut ehash,
+                &mut reductions,
+                &mut is_ready,
+            );
+        });
+        u
+    };
+    let limits = {
+        let mut l = LimitsType::new();
+        l.depth = Some(search_depth);
+        if search_nodes != 0 {
+            l.nodes = Some(search_nodes);
+        }
+        l
+    };
+    let ponder_mode = false;
+    let hide_all_output = true;
+    let root = Position::new();
+    // The leaves which have no moves to add (e.g. mated). They are searched only once.
+    let mut searched = std::collections::HashSet::new();
+    let mut count = 0;
+    loop {
+        let leaves = book
+            .leaves(&root, max_ply)
+            .into_iter()
+            .filter(|sfen| !searched.contains(&Book::key_from_sfen(sfen)))
+            .collect::<Vec<_>>();
+        if leaves.is_empty() {
+            break;
+        }
+        for sfen in leaves {
+            searched.insert(Book::key_from_sfen(&sfen));
+            let pos = Position::new_from_sfen(&sfen).unwrap();
+            let mut limits = limits.clone();
+            limits.start_time = Some(std::time::Instant::now());
+            thread_pool.start_thinking(&pos, &mut tt, limits, &usi_options, ponder_mode, hide_all_output);
+            thread_pool.wait_for_search_finished();
+            for (m, value) in searched_book_moves(&thread_pool.last_pv_root_moves.lock().unwrap()) {
+                book.insert(
+                    sfen.clone(),
+                    m,
+                    Info {
+                        value,
+                        win: 0,
+                        lose: 0,
+                        count_only: false,
+                    },
+                );
+            }
+            count += 1;
+            if count % flush_interval.max(1) == 0 {
+                book.back_propagate(&root);
+                book.to_file(book_file_path)
+                    .map_err(|e| anyhow!("{}: {}", e, book_file_path))?;
+                println!("info string searched {} {}", count, book.stats());
+            }
+        }
+    }
+    book.back_propagate(&root);
+    book.to_file(book_file_path)
+        .map_err(|e| anyhow!("{}: {}", e, book_file_path))?;
+    println!("info string searched {} {}", count, book.stats());
+    Ok(())
 }
 
 #[cfg(test)]
@@ -71,6 +982,7 @@ mod tests {
                     value: Value(36),
                     win: 6,
                     lose: 4,
+                    count_only: false,
                 },
             );
             b.insert(
@@ -80,6 +992,7 @@ mod tests {
                     value: Value(99),
                     win: 99,
                     lose: 3,
+                    count_only: false,
                 },
             );
             b.insert(
@@ -89,6 +1002,7 @@ mod tests {
                     value: Value(20),
                     win: 1,
                     lose: 10,
+                    count_only: false,
                 },
             );
             // overwrite
@@ -99,6 +1013,7 @@ mod tests {
                     value: Value(36),
                     win: 3,
                     lose: 9,
+                    count_only: false,
                 },
             );
             let m = Move::new_from_usi_str("2g2f", &pos).unwrap();
@@ -111,10 +1026,11 @@ mod tests {
                     value: Value(-99),
                     win: 1,
                     lose: 2,
+                    count_only: false,
                 },
             );
             assert_eq!(
-                r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w - 2":{"3c3d":{"value":-99,"win":1,"lose":2}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1":{"2g2f":{"value":36,"win":6,"lose":4},"6i7h":{"value":20,"win":1,"lose":10},"7g7f":{"value":36,"win":3,"lose":9}}}"#,
+                r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w -":{"3c3d":{"value":-99,"win":1,"lose":2}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -":{"2g2f":{"value":36,"win":6,"lose":4},"6i7h":{"value":20,"win":1,"lose":10},"7g7f":{"value":36,"win":3,"lose":9}}}"#,
                 serde_json::to_string(&b).unwrap(),
             );
         })
@@ -138,6 +1054,7 @@ mod tests {
                         value: Value(36),
                         win: 70,
                         lose: 30,
+                        count_only: false,
                     },
                 );
                 b.insert(
@@ -147,6 +1064,7 @@ mod tests {
                         value: Value(99),
                         win: 30,
                         lose: 10,
+                        count_only: false,
                     },
                 );
 
@@ -195,7 +1113,7 @@ mod tests {
             let path = std::path::Path::new("test/book.json");
             let book = Book::from_file(path).unwrap();
             assert_eq!(
-                r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w - 2":{"3c3d":{"value":-99,"win":1,"lose":2}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1":{"2g2f":{"value":36,"win":6,"lose":4},"6i7h":{"value":20,"win":1,"lose":10},"7g7f":{"value":36,"win":3,"lose":9}}}"#,
+                r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w -":{"3c3d":{"value":-99,"win":1,"lose":2}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -":{"2g2f":{"value":36,"win":6,"lose":4},"6i7h":{"value":20,"win":1,"lose":10},"7g7f":{"value":36,"win":3,"lose":9}}}"#,
                 serde_json::to_string(&book).unwrap(),
             );
         })
@@ -203,4 +1121,485 @@ mod tests {
         .join()
         .unwrap();
     }
+
+    #[test]
+    fn test_book_migrate() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let json = r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1":{"2g2f":{"value":36,"win":6,"lose":4},"7g7f":{"value":10,"win":1,"lose":1}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 3":{"7g7f":{"value":50,"win":3,"lose":2}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w - 2":{"3c3d":{"value":-99,"win":1,"lose":2}}}"#;
+                let book: Book = serde_json::from_str(json).unwrap();
+                let (book, stats) = book.migrate();
+                assert_eq!(
+                    stats,
+                    BookStats {
+                        positions: 2,
+                        moves: 3,
+                        merged_positions: 1,
+                        collided_moves: 1,
+                    }
+                );
+                assert_eq!(
+                    r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w -":{"3c3d":{"value":-99,"win":1,"lose":2}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -":{"2g2f":{"value":36,"win":6,"lose":4},"7g7f":{"value":50,"win":4,"lose":3}}}"#,
+                    serde_json::to_string(&book).unwrap(),
+                );
+
+                // Any game ply hits the book.
+                let pos = Position::new_from_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 123").unwrap();
+                assert!(book.probe(&pos, &mut rand::thread_rng()).is_some());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_book_probe_with_settings() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+                let pos = Position::new_from_sfen(sfen).unwrap();
+                let mut b = Book::new();
+                for &(usi_move, value, win, lose) in &[("2g2f", 50, 0, 1), ("7g7f", 40, 30, 10), ("5g5f", -200, 100, 0)] {
+                    b.insert(
+                        sfen.to_string(),
+                        Move::new_from_usi_str(usi_move, &pos).unwrap(),
+                        Info {
+                            value: Value(value),
+                            win,
+                            lose,
+                            count_only: false,
+                        },
+                    );
+                }
+                let mut rng = rand::thread_rng();
+                let probe = |settings: &BookProbeSettings, rng: &mut ThreadRng| {
+                    let (m, value) = b.probe_with_settings(&pos, settings, rng).unwrap();
+                    (m.to_usi_string(), value)
+                };
+
+                let settings = BookProbeSettings {
+                    policy: BookPolicy::BestValue,
+                    ..Default::default()
+                };
+                for _ in 0..100 {
+                    assert_eq!(probe(&settings, &mut rng), ("2g2f".to_string(), Value(50)));
+                }
+
+                // 2g2f has no wins.
+                let settings = BookProbeSettings {
+                    policy: BookPolicy::WinCount,
+                    ..Default::default()
+                };
+                for _ in 0..100 {
+                    assert_ne!(probe(&settings, &mut rng).0, "2g2f");
+                }
+
+                // 5g5f is far worse than the best value.
+                let settings = BookProbeSettings {
+                    policy: BookPolicy::Uniform,
+                    narrow_margin: Some(Value(100)),
+                    ..Default::default()
+                };
+                for _ in 0..100 {
+                    assert_ne!(probe(&settings, &mut rng).0, "5g5f");
+                }
+
+                let settings = BookProbeSettings {
+                    policy: BookPolicy::BestValue,
+                    min_games: 40,
+                    ..Default::default()
+                };
+                for _ in 0..100 {
+                    assert_eq!(probe(&settings, &mut rng), ("7g7f".to_string(), Value(40)));
+                }
+
+                let settings = BookProbeSettings {
+                    policy: BookPolicy::Uniform,
+                    min_games: 1000,
+                    ..Default::default()
+                };
+                assert!(b.probe_with_settings(&pos, &settings, &mut rng).is_none());
+
+                // The moves whose results aren't known are weighted by the number of games.
+                let mut b = Book::new();
+                for &(usi_move, games) in &[("2g2f", 0), ("7g7f", 10)] {
+                    b.insert(
+                        sfen.to_string(),
+                        Move::new_from_usi_str(usi_move, &pos).unwrap(),
+                        Info {
+                            value: Value::ZERO,
+                            win: games,
+                            lose: 0,
+                            count_only: true,
+                        },
+                    );
+                }
+                for policy in [BookPolicy::WinCount, BookPolicy::WinRate] {
+                    let settings = BookProbeSettings {
+                        policy,
+                        ..Default::default()
+                    };
+                    for _ in 0..100 {
+                        let (m, _) = b.probe_with_settings(&pos, &settings, &mut rng).unwrap();
+                        assert_eq!(m.to_usi_string(), "7g7f");
+                    }
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_book_maker() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let games = [
+                    (&["2g2f", "3c3d"][..], Some(GameResult::BlackWin)),
+                    (&["2g2f", "8c8d"][..], Some(GameResult::WhiteWin)),
+                    (&["2g2f", "3c3d"][..], None),
+                    (&["7g7f"][..], Some(GameResult::Draw)),
+                ];
+                let make_book = |max_ply: i32, min_frequency: u64| {
+                    let mut book_maker = BookMaker::new(max_ply);
+                    for (usi_moves, result) in games.iter() {
+                        let start = Position::new();
+                        let mut pos = Position::new();
+                        let mut moves = vec![];
+                        for usi_move in usi_moves.iter() {
+                            let m = Move::new_from_usi_str(usi_move, &pos).unwrap();
+                            pos.do_move(m, pos.gives_check(m));
+                            moves.push(m);
+                        }
+                        book_maker.add_game(&start, &moves, *result);
+                    }
+                    serde_json::to_string(&book_maker.into_book(min_frequency)).unwrap()
+                };
+                assert_eq!(
+                    make_book(1, 1),
+                    r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -":{"2g2f":{"value":0,"win":1,"lose":1},"7g7f":{"value":0,"win":0,"lose":0}}}"#,
+                );
+                assert_eq!(
+                    make_book(2, 2),
+                    r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w -":{"3c3d":{"value":0,"win":0,"lose":1}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -":{"2g2f":{"value":0,"win":1,"lose":1}}}"#,
+                );
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_book_leaves_and_back_propagate() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let root = Position::new();
+                let mut pos = Position::new();
+                let mut b = Book::new();
+                let info = |value: i32| Info {
+                    value: Value(value),
+                    win: 0,
+                    lose: 0,
+                    count_only: false,
+                };
+                b.insert(pos.to_sfen(), Move::new_from_usi_str("2g2f", &pos).unwrap(), info(0));
+                b.insert(pos.to_sfen(), Move::new_from_usi_str("7g7f", &pos).unwrap(), info(-300));
+                let m = Move::new_from_usi_str("2g2f", &pos).unwrap();
+                pos.do_move(m, pos.gives_check(m));
+                b.insert(pos.to_sfen(), Move::new_from_usi_str("3c3d", &pos).unwrap(), info(50));
+                b.insert(pos.to_sfen(), Move::new_from_usi_str("8c8d", &pos).unwrap(), info(100));
+
+                let mut leaves = b.leaves(&root, 3);
+                leaves.sort();
+                assert_eq!(
+                    leaves,
+                    vec![
+                        "lnsgkgsnl/1r5b1/p1ppppppp/1p7/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL b - 3",
+                        "lnsgkgsnl/1r5b1/pppppp1pp/6p2/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL b - 3",
+                        "lnsgkgsnl/1r5b1/ppppppppp/9/9/2P6/PP1PPPPPP/1B5R1/LNSGKGSNL w - 2",
+                    ]
+                );
+                // The root is in the book and max_ply stops expanding.
+                assert!(b.leaves(&root, 1).is_empty());
+
+                b.back_propagate(&root);
+                let key = Book::key(&root);
+                assert_eq!(
+                    b.0[&key][&Move::new_from_usi_str("2g2f", &root).unwrap().to_usi()].value,
+                    Value(-100)
+                );
+                assert_eq!(
+                    b.0[&key][&Move::new_from_usi_str("7g7f", &root).unwrap().to_usi()].value,
+                    Value(-300)
+                );
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_binary_book() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let json_path = std::path::Path::new("test/book.json");
+                let book = Book::from_file(json_path).unwrap();
+                let binary_book = BinaryBook::from_book(&book).unwrap();
+                assert_eq!(
+                    binary_book.stats(),
+                    BookStats {
+                        positions: 2,
+                        moves: 4,
+                        ..Default::default()
+                    }
+                );
+
+                let binary_path = std::env::temp_dir().join(format!("apery_test_binary_book_{}.bin", std::process::id()));
+                binary_book.to_file(&binary_path).unwrap();
+                assert!(BinaryBook::is_binary_book_file(&binary_path).unwrap());
+                assert!(!BinaryBook::is_binary_book_file(json_path).unwrap());
+                let any_book = AnyBook::from_file(&binary_path).unwrap();
+                std::fs::remove_file(&binary_path).unwrap();
+                let binary_book = match any_book {
+                    AnyBook::Binary(binary_book) => binary_book,
+                    _ => unreachable!(),
+                };
+
+                let pos = Position::new();
+                let settings = BookProbeSettings {
+                    policy: BookPolicy::BestValue,
+                    ..Default::default()
+                };
+                let mut rng = rand::thread_rng();
+                for _ in 0..100 {
+                    let (m, value) = binary_book.probe_with_settings(&pos, &settings, &mut rng).unwrap();
+                    assert!(["2g2f", "7g7f"].contains(&m.to_usi_string().as_str()));
+                    assert_eq!(value, Value(36));
+                }
+
+                let (restored, unreached) = binary_book.to_book(&pos);
+                assert_eq!(unreached, 0);
+                assert_eq!(
+                    serde_json::to_string(&restored).unwrap(),
+                    serde_json::to_string(&book).unwrap()
+                );
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_searched_book_moves() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let pos = Position::new();
+                let mut root_moves = ["2g2f", "7g7f", "5g5f"]
+                    .iter()
+                    .map(|usi_move| RootMove::new(Move::new_from_usi_str(usi_move, &pos).unwrap()))
+                    .collect::<Vec<_>>();
+                root_moves[0].score = Value(30);
+                root_moves[0].previous_score = Value(20);
+                // Not searched in the last iteration.
+                root_moves[1].previous_score = Value(10);
+                let moves = searched_book_moves(&root_moves)
+                    .into_iter()
+                    .map(|(m, value)| (m.to_usi_string(), value))
+                    .collect::<Vec<_>>();
+                assert_eq!(moves, [("2g2f".to_string(), Value(30)), ("7g7f".to_string(), Value(10))]);
+
+                // The search stopped by the nodes limit in the middle of MultiPV.
+                let mut thread_pool = ThreadPool::new();
+                let mut tt = TranspositionTable::new();
+                #[cfg(feature = "kppt")]
+                let mut ehash = EvalHash::new();
+                let mut reductions = Reductions::new();
+                let mut is_ready = false;
+                let mut usi_options = UsiOptions::new();
+                // No evaluation function binaries. Then we do nothing like test_start_thinking.
+                #[cfg(feature = "kppt")]
+                if load_evaluate_files(&usi_options.get_string(UsiOptions::EVAL_DIR)).is_err() {
+                    return;
+                }
+                tt.resize(16, &mut thread_pool);
+                #[cfg(feature = "kppt")]
+                ehash.resize(16, &mut thread_pool);
+                thread_pool.set(
+                    1,
+                    &mut tt,
+                    #[cfg(feature = "kppt")]
+                    &mut ehash,
+                    &mut reductions,
+                );
+                setoption(
+                    &["name", UsiOptions::MULTI_PV, "value", "30"],
+                    &mut usi_options,
+                    &mut thread_pool,
+                    &mut tt,
+                    #[cfg(feature = "kppt")]
+                    &mut ehash,
+                    &mut reductions,
+                    &mut is_ready,
+                );
+                let limits = {
+                    let mut l = LimitsType::new();
+                    l.depth = Some(10);
+                    l.nodes = Some(1000);
+                    l.start_time = Some(std::time::Instant::now());
+                    l
+                };
+                let ponder_mode = false;
+                let hide_all_output = true;
+                thread_pool.start_thinking(&pos, &mut tt, limits, &usi_options, ponder_mode, hide_all_output);
+                thread_pool.wait_for_search_finished();
+                let root_moves = thread_pool.last_pv_root_moves.lock().unwrap().clone();
+                assert!(root_moves.iter().any(|rm| rm.score == -Value::INFINITE));
+                let moves = searched_book_moves(&root_moves);
+                assert!(!moves.is_empty());
+                assert!(moves.iter().all(|&(_, value)| value != -Value::INFINITE));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_book_yaneuraou_db() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let db = "#YANEURAOU-DB2016 1.00\n\
+                          sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1\n\
+                          7g7f 3c3d 40 20 5\n\
+                          2g2f none 30 18 3\n\
+                          // comment\n\
+                          sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 3\n\
+                          7g7f 3c3d 50 20 2\n";
+                let (book, stats) = Book::from_yaneuraou_db_reader(db.as_bytes()).unwrap().migrate();
+                assert_eq!(
+                    stats,
+                    BookStats {
+                        positions: 1,
+                        moves: 2,
+                        merged_positions: 1,
+                        collided_moves: 1,
+                    }
+                );
+                assert_eq!(
+                    r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -":{"2g2f":{"value":27,"win":3,"lose":0,"count_only":true},"7g7f":{"value":36,"win":7,"lose":0,"count_only":true}}}"#,
+                    serde_json::to_string(&book).unwrap(),
+                );
+                assert!(Book::from_yaneuraou_db_reader("#YANEURAOU-DB2016 1.00\n7g7f none 0 0 1\n".as_bytes()).is_err());
+
+                let book = Book::from_file("test/book.json").unwrap();
+                let path = std::env::temp_dir().join(format!("apery_test_book_yaneuraou_db_{}.db", std::process::id()));
+                book.to_yaneuraou_db_file(&path).unwrap();
+                let db = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(
+                    db,
+                    "#YANEURAOU-DB2016 1.00\n\
+                     sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w - 2\n\
+                     3c3d none -110 0 3\n\
+                     sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1\n\
+                     7g7f none 40 0 12\n\
+                     2g2f 3c3d 40 0 10\n\
+                     6i7h none 22 0 11\n"
+                );
+                let imported = Book::from_file(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                // The wins and losses are lost. e.g. 6 wins and 4 losses of 2g2f come back as 10 games.
+                assert_eq!(
+                    r#"{"lnsgkgsnl/1r5b1/ppppppppp/9/9/7P1/PPPPPPP1P/1B5R1/LNSGKGSNL w -":{"3c3d":{"value":-99,"win":3,"lose":0,"count_only":true}},"lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b -":{"2g2f":{"value":36,"win":10,"lose":0,"count_only":true},"6i7h":{"value":20,"win":11,"lose":0,"count_only":true},"7g7f":{"value":36,"win":12,"lose":0,"count_only":true}}}"#,
+                    serde_json::to_string(&imported).unwrap(),
+                );
+                // The binary book keeps count_only.
+                let (restored, _) = BinaryBook::from_book(&imported).unwrap().to_book(&Position::new());
+                assert_eq!(
+                    serde_json::to_string(&restored).unwrap(),
+                    serde_json::to_string(&imported).unwrap()
+                );
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_book_learn() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let book = Book::from_file("test/book.json").unwrap();
+                let binary_book = BinaryBook::from_book(&book).unwrap();
+                let sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+                let pos = Position::new_from_sfen(sfen).unwrap();
+                let m = Move::new_from_usi_str("2g2f", &pos).unwrap();
+                let not_in_book = Move::new_from_usi_str("5g5f", &pos).unwrap();
+                let mut rng = rand::thread_rng();
+                for (i, mut any_book) in [AnyBook::Json(book), AnyBook::Binary(binary_book)].into_iter().enumerate() {
+                    assert!(any_book.learn(sfen, m, true).unwrap());
+                    assert!(any_book.learn(sfen, m, false).unwrap());
+                    assert!(any_book.learn(sfen, m, false).unwrap());
+                    assert!(!any_book.learn(sfen, not_in_book, true).unwrap());
+
+                    // Save, reload and probe.
+                    let path = std::env::temp_dir().join(format!("apery_test_book_learn_{}_{}", std::process::id(), i));
+                    any_book.to_file(&path).unwrap();
+                    let any_book = AnyBook::from_file(&path).unwrap();
+                    std::fs::remove_file(&path).unwrap();
+                    let info = match &any_book {
+                        AnyBook::Json(book) => book.0[&Book::key(&pos)][&m.to_usi()],
+                        AnyBook::Binary(book) => book.entries(pos.key()).iter().find(|e| e.mv16 == m.to_u16()).unwrap().info(),
+                        AnyBook::YaneuraOuDb(_) => unreachable!(),
+                    };
+                    assert_eq!(
+                        info,
+                        Info {
+                            value: Value(36),
+                            win: 7,
+                            lose: 6,
+                            count_only: false,
+                        }
+                    );
+                    // Only 2g2f has 13 games.
+                    let settings = BookProbeSettings {
+                        min_games: 13,
+                        ..Default::default()
+                    };
+                    for _ in 0..10 {
+                        assert_eq!(any_book.probe_with_settings(&pos, &settings, &mut rng).unwrap().0, m);
+                    }
+                }
+
+                // YaneuraOu db doesn't keep the wins and losses.
+                let db_path = std::env::temp_dir().join(format!("apery_test_book_learn_{}.db", std::process::id()));
+                Book::from_file("test/book.json")
+                    .unwrap()
+                    .to_yaneuraou_db_file(&db_path)
+                    .unwrap();
+                let mut any_book = AnyBook::from_file(&db_path).unwrap();
+                std::fs::remove_file(&db_path).unwrap();
+                assert!(matches!(any_book, AnyBook::YaneuraOuDb(_)));
+                assert!(any_book.learn(sfen, m, false).is_err());
+
+                // The entries imported from YaneuraOu db are not learned in the other formats either.
+                let book = match any_book {
+                    AnyBook::YaneuraOuDb(book) => book,
+                    _ => unreachable!(),
+                };
+                let binary_book = BinaryBook::from_book(&book).unwrap();
+                for mut any_book in [AnyBook::Json(book), AnyBook::Binary(binary_book)] {
+                    assert!(!any_book.learn(sfen, m, false).unwrap());
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
 }
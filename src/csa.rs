@@ -0,0 +1,593 @@
+use crate::hand::*;
+use crate::huffman_code::*;
+use crate::movetypes::*;
+use crate::position::*;
+use crate::sfen::*;
+use crate::types::*;
+use anyhow::{anyhow, Context, Result};
+use thiserror::Error;
+
+// CSA standard file format version 2.2.
+// http://www2.computer-shogi.org/protocol/record_v22.html
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsaMove {
+    pub mv: Move,
+    pub time: Option<std::time::Duration>, // consumed time. "T" line.
+    pub comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsaSpecialMove {
+    pub name: String, // e.g. "%TORYO"
+    pub time: Option<std::time::Duration>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CsaRecord {
+    pub version: Option<String>,
+    pub black_player: Option<String>,
+    pub white_player: Option<String>,
+    pub information: Vec<(String, String)>, // "$EVENT:..." etc. as ("EVENT", "...").
+    pub start_sfen: String,
+    pub moves: Vec<CsaMove>,
+    pub special_move: Option<CsaSpecialMove>,
+    pub result: Option<GameResult>, // decided by special_move.
+    pub comments: Vec<String>,      // The comments which aren't after any move.
+}
+
+// special is a CSA special move such as "%TORYO". side_to_move is the side to move when the special move appears.
+// Return None if the game has no winner nor draw (e.g. "%CHUDAN").
+pub fn csa_special_move_to_game_result(special: &str, side_to_move: Color) -> Option<GameResult> {
+    let win = |c: Color| {
+        if c == Color::BLACK {
+            GameResult::BlackWin
+        } else {
+            GameResult::WhiteWin
+        }
+    };
+    match special {
+        "%TORYO" | "%TSUMI" | "%TIME_UP" | "%ILLEGAL_MOVE" => Some(win(side_to_move.inverse())),
+        "%KACHI" => Some(win(side_to_move)),
+        "%+ILLEGAL_ACTION" => Some(GameResult::WhiteWin),
+        "%-ILLEGAL_ACTION" => Some(GameResult::BlackWin),
+        "%SENNICHITE" | "%HIKIWAKE" | "%JISHOGI" | "%MAX_MOVES" => Some(GameResult::Draw),
+        _ => None,
+    }
+}
+
+#[rustfmt::skip]
+#[derive(Debug, Error)]
+pub enum CsaBoardError {
+    #[error(r##"invalid line. found "{line}"."##)]
+    InvalidLine { line: String },
+    #[error(r##"invalid number of squares. found "{line}"."##)]
+    InvalidNumberOfSquares { line: String },
+    #[error(r##"invalid square "{token}" in "{line}"."##)]
+    InvalidSquare { token: String, line: String },
+    #[error(r##"invalid piece "{token}" in "{line}"."##)]
+    InvalidPiece { token: String, line: String },
+    #[error(r##"invalid hand piece "{token}" in "{line}"."##)]
+    InvalidHandPiece { token: String, line: String },
+    #[error(r##"no piece to remove "{token}" in "{line}"."##)]
+    NoPieceToRemove { token: String, line: String },
+    #[error(r##"the square is already occupied "{token}" in "{line}"."##)]
+    SquareAlreadyOccupied { token: String, line: String },
+    #[error(r##""AL" twice. found "{line}"."##)]
+    AllRemainingTwice { line: String },
+    #[error("side to move is nothing.")]
+    SideToMoveIsNothing,
+    #[error("{0}")]
+    Sfen(#[from] SfenError),
+}
+
+// The initial position of the CSA record. It's converted to sfen after all the lines are read.
+struct CsaBoard {
+    board: [Piece; Square::NUM],
+    hands: [[usize; HAND_PIECE_TYPES.len()]; Color::NUM],
+    all_remaining: Option<Color>, // "00AL"
+}
+
+impl CsaBoard {
+    fn new() -> CsaBoard {
+        CsaBoard {
+            board: [Piece::EMPTY; Square::NUM],
+            hands: [[0; HAND_PIECE_TYPES.len()]; Color::NUM],
+            all_remaining: None,
+        }
+    }
+    fn new_hirate() -> CsaBoard {
+        let pos = Position::new();
+        let mut board = CsaBoard::new();
+        for sq in (0..Square::NUM as i32).map(Square) {
+            board.board[sq.0 as usize] = pos.piece_on(sq);
+        }
+        board
+    }
+    fn square(file: u8, rank: u8) -> Option<Square> {
+        let file = File::new_from_csa_char(file as char)?;
+        let rank = Rank::new_from_csa_char(rank as char)?;
+        Some(Square::new(file, rank))
+    }
+    fn hand_index(pt: PieceType) -> Option<usize> {
+        HAND_PIECE_TYPES.iter().position(|x| *x == pt)
+    }
+    // Apply the statement of the initial position to board.
+    // Return false if the statement isn't about the initial position. The side to move line isn't handled here.
+    fn apply(board: &mut Option<CsaBoard>, statement: &str) -> Result<bool, CsaBoardError> {
+        // The board lines are parsed by bytes.
+        if statement.starts_with('P') && !statement.is_ascii() {
+            return Err(CsaBoardError::InvalidLine {
+                line: statement.to_string(),
+            });
+        }
+        if statement.starts_with("PI") {
+            board.get_or_insert_with(CsaBoard::new).set_pi(statement)?;
+        } else if statement.starts_with("P+") || statement.starts_with("P-") {
+            board.get_or_insert_with(CsaBoard::new).set_pieces(statement)?;
+        } else if statement.len() >= 2 && statement.starts_with('P') && (b'1'..=b'9').contains(&statement.as_bytes()[1]) {
+            board.get_or_insert_with(CsaBoard::new).set_rank(statement)?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+    // "PI82HI22KA"
+    fn set_pi(&mut self, line: &str) -> Result<(), CsaBoardError> {
+        *self = CsaBoard::new_hirate();
+        let bytes = &line.as_bytes()[2..];
+        for chunk in bytes.chunks(4) {
+            let s = String::from_utf8_lossy(chunk);
+            let sq = match chunk {
+                [file, rank, _, _] => Self::square(*file, *rank),
+                _ => None,
+            }
+            .ok_or_else(|| CsaBoardError::InvalidSquare {
+                token: s.to_string(),
+                line: line.to_string(),
+            })?;
+            let pt = PieceType::new_from_csa_str(&s[2..]).ok_or_else(|| CsaBoardError::InvalidPiece {
+                token: s.to_string(),
+                line: line.to_string(),
+            })?;
+            if PieceType::new(self.board[sq.0 as usize]) != pt {
+                return Err(CsaBoardError::NoPieceToRemove {
+                    token: s.to_string(),
+                    line: line.to_string(),
+                });
+            }
+            self.board[sq.0 as usize] = Piece::EMPTY;
+        }
+        Ok(())
+    }
+    // "P1-KY-KE-GI-KI-OU-KI-GI-KE-KY"
+    fn set_rank(&mut self, line: &str) -> Result<(), CsaBoardError> {
+        let rank = Rank::new_from_csa_char(line.as_bytes()[1] as char).unwrap();
+        // Trailing spaces may be removed.
+        let cells = format!("{:<27}", &line[2..]);
+        if cells.len() != 27 {
+            return Err(CsaBoardError::InvalidNumberOfSquares { line: line.to_string() });
+        }
+        for (file, cell) in File::ALL_FROM_LEFT.iter().zip(cells.as_bytes().chunks(3)) {
+            let cell = String::from_utf8_lossy(cell);
+            let pc = match cell.as_ref() {
+                " * " | "   " => Piece::EMPTY,
+                _ => Self::piece(&cell).ok_or_else(|| CsaBoardError::InvalidPiece {
+                    token: cell.to_string(),
+                    line: line.to_string(),
+                })?,
+            };
+            self.board[Square::new(*file, rank).0 as usize] = pc;
+        }
+        Ok(())
+    }
+    // "+FU"
+    fn piece(s: &str) -> Option<Piece> {
+        let c = match s.get(..1)? {
+            "+" => Color::BLACK,
+            "-" => Color::WHITE,
+            _ => return None,
+        };
+        let pt = PieceType::new_from_csa_str(s.get(1..)?)?;
+        Some(Piece::new(c, pt))
+    }
+    // "P+63TO00KA", "P-00AL"
+    fn set_pieces(&mut self, line: &str) -> Result<(), CsaBoardError> {
+        let c = if line.as_bytes()[1] == b'+' {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        };
+        for chunk in line.as_bytes()[2..].chunks(4) {
+            let s = String::from_utf8_lossy(chunk);
+            if chunk.len() != 4 {
+                return Err(CsaBoardError::InvalidPiece {
+                    token: s.to_string(),
+                    line: line.to_string(),
+                });
+            }
+            if &s[..2] == "00" {
+                if &s[2..] == "AL" {
+                    if self.all_remaining.is_some() {
+                        return Err(CsaBoardError::AllRemainingTwice { line: line.to_string() });
+                    }
+                    self.all_remaining = Some(c);
+                    continue;
+                }
+                let index = PieceType::new_from_csa_str(&s[2..])
+                    .and_then(Self::hand_index)
+                    .ok_or_else(|| CsaBoardError::InvalidHandPiece {
+                        token: s.to_string(),
+                        line: line.to_string(),
+                    })?;
+                self.hands[c.0 as usize][index] += 1;
+            } else {
+                let sq = Self::square(chunk[0], chunk[1]).ok_or_else(|| CsaBoardError::InvalidSquare {
+                    token: s.to_string(),
+                    line: line.to_string(),
+                })?;
+                let pt = PieceType::new_from_csa_str(&s[2..]).ok_or_else(|| CsaBoardError::InvalidPiece {
+                    token: s.to_string(),
+                    line: line.to_string(),
+                })?;
+                if self.board[sq.0 as usize] != Piece::EMPTY {
+                    return Err(CsaBoardError::SquareAlreadyOccupied {
+                        token: s.to_string(),
+                        line: line.to_string(),
+                    });
+                }
+                self.board[sq.0 as usize] = Piece::new(c, pt);
+            }
+        }
+        Ok(())
+    }
+    fn to_sfen(&self, side_to_move: Color) -> String {
+        let mut hands = self.hands;
+        if let Some(c) = self.all_remaining {
+            for (index, pt) in HAND_PIECE_TYPES.iter().enumerate() {
+                let on_board = self
+                    .board
+                    .iter()
+                    .filter(|pc| **pc != Piece::EMPTY && !pc.is_king() && PieceType::new(**pc).to_demote_if_possible() == *pt)
+                    .count();
+                let in_hands = hands[0][index] + hands[1][index];
+                hands[c.0 as usize][index] += max_piece_num(*pt).saturating_sub(on_board + in_hands);
+            }
+        }
+        let mut s = "".to_string();
+        for rank in Rank::ALL_FROM_UPPER.iter() {
+            if !s.is_empty() {
+                s += "/";
+            }
+            let mut empty_squares = 0;
+            for file in File::ALL_FROM_LEFT.iter() {
+                let pc = self.board[Square::new(*file, *rank).0 as usize];
+                if pc == Piece::EMPTY {
+                    empty_squares += 1;
+                } else {
+                    if empty_squares != 0 {
+                        s += &empty_squares.to_string();
+                        empty_squares = 0;
+                    }
+                    s += pc.to_usi_str();
+                }
+            }
+            if empty_squares != 0 {
+                s += &empty_squares.to_string();
+            }
+        }
+        s += if side_to_move == Color::BLACK { " b " } else { " w " };
+        let mut hand_str = "".to_string();
+        for c in [Color::BLACK, Color::WHITE].iter() {
+            for (index, pt) in HAND_PIECE_TYPES.iter().enumerate() {
+                let num = hands[c.0 as usize][index];
+                if num == 0 {
+                    continue;
+                }
+                if num > 1 {
+                    hand_str += &num.to_string();
+                }
+                hand_str += Piece::new(*c, *pt).to_usi_str();
+            }
+        }
+        if hand_str.is_empty() {
+            hand_str = "-".to_string();
+        }
+        s += &hand_str;
+        s += " 1";
+        s
+    }
+}
+
+fn parse_time(s: &str) -> Result<std::time::Duration> {
+    let secs = s[1..]
+        .parse::<f64>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as consumed time."#, s))?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(anyhow!(r#"cannot parse "{}" as consumed time."#, s));
+    }
+    Ok(std::time::Duration::from_secs_f64(secs))
+}
+
+// The sfen of a CSA board diagram. e.g. the output of "PositionBase::to_csa_string".
+// The lines after the side to move line (e.g. the moves of a game record) are ignored.
+pub fn csa_board_to_sfen(csa: &str) -> Result<String, CsaBoardError> {
+    let mut board = None;
+    for line in csa.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with('\'') {
+            continue;
+        }
+        for statement in line.split(',') {
+            let statement = statement.trim_end();
+            if statement.is_empty() || CsaBoard::apply(&mut board, statement)? {
+                continue;
+            }
+            match statement {
+                "+" => return Ok(board.unwrap_or_else(CsaBoard::new_hirate).to_sfen(Color::BLACK)),
+                "-" => return Ok(board.unwrap_or_else(CsaBoard::new_hirate).to_sfen(Color::WHITE)),
+                // The version, the player names and the information are allowed before the position.
+                _ if statement.starts_with('V') || statement.starts_with('N') || statement.starts_with('$') => {}
+                _ => {
+                    return Err(CsaBoardError::InvalidLine {
+                        line: statement.to_string(),
+                    })
+                }
+            }
+        }
+    }
+    Err(CsaBoardError::SideToMoveIsNothing)
+}
+
+impl CsaRecord {
+    // Parse one game record. The records after the separator "/" are ignored.
+    pub fn parse(csa: &[u8]) -> Result<CsaRecord> {
+        Ok(Self::parse_all(csa)?.swap_remove(0))
+    }
+    // Parse the game records separated by "/".
+    pub fn parse_all(csa: &[u8]) -> Result<Vec<CsaRecord>> {
+        let mut records = vec![];
+        let mut lines = vec![];
+        for line in csa.split(|num_as_ascii| *num_as_ascii == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end_matches('\r');
+            if line == "/" {
+                records.push(Self::parse_lines(&lines)?);
+                lines.clear();
+            } else {
+                lines.push(line.to_string());
+            }
+        }
+        records.push(Self::parse_lines(&lines)?);
+        Ok(records)
+    }
+    fn parse_lines(lines: &[String]) -> Result<CsaRecord> {
+        let mut record = CsaRecord::default();
+        let mut board: Option<CsaBoard> = None;
+        let mut pos: Option<Position> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.as_str();
+            if let Some(comment) = line.strip_prefix('\'') {
+                // The comment is for the last move.
+                match (record.special_move.as_mut(), record.moves.last_mut()) {
+                    (Some(special_move), _) => special_move.comments.push(comment.to_string()),
+                    (None, Some(m)) => m.comments.push(comment.to_string()),
+                    (None, None) => record.comments.push(comment.to_string()),
+                }
+                continue;
+            }
+            // Multiple statements can be in a line separated by ",".
+            for statement in line.split(',') {
+                let statement = statement.trim_end();
+                if statement.is_empty() {
+                    continue;
+                }
+                match &mut pos {
+                    None => {
+                        if statement.starts_with('V') {
+                            record.version = Some(statement.to_string());
+                        } else if let Some(name) = statement.strip_prefix("N+") {
+                            record.black_player = Some(name.to_string());
+                        } else if let Some(name) = statement.strip_prefix("N-") {
+                            record.white_player = Some(name.to_string());
+                        } else if let Some(info) = statement.strip_prefix('$') {
+                            let (key, value) = info.split_once(':').unwrap_or((info, ""));
+                            record.information.push((key.to_string(), value.to_string()));
+                        } else if statement == "+" || statement == "-" {
+                            let side_to_move = if statement == "+" { Color::BLACK } else { Color::WHITE };
+                            record.start_sfen = board.get_or_insert_with(CsaBoard::new_hirate).to_sfen(side_to_move);
+                            pos = Some(
+                                Position::new_from_sfen(&record.start_sfen)
+                                    .map_err(|e| anyhow!("invalid initial position at line {}. {}", i + 1, e))?,
+                            );
+                        } else {
+                            CsaBoard::apply(&mut board, statement).with_context(|| anyhow!("at line {}.", i + 1))?;
+                        }
+                    }
+                    Some(pos) => {
+                        if statement.starts_with('%') {
+                            record.result = csa_special_move_to_game_result(statement, pos.side_to_move());
+                            record.special_move = Some(CsaSpecialMove {
+                                name: statement.to_string(),
+                                time: None,
+                                comments: vec![],
+                            });
+                        } else if statement.starts_with('T') {
+                            let time = Some(parse_time(statement).with_context(|| anyhow!("at line {}.", i + 1))?);
+                            match (record.special_move.as_mut(), record.moves.last_mut()) {
+                                (Some(special_move), _) => special_move.time = time,
+                                (None, Some(m)) => m.time = time,
+                                (None, None) => {}
+                            }
+                        } else if statement.starts_with('+') || statement.starts_with('-') {
+                            if record.special_move.is_some() {
+                                return Err(anyhow!(r#"the move "{}" after the game end at line {}."#, statement, i + 1));
+                            }
+                            let c = if statement.starts_with('+') {
+                                Color::BLACK
+                            } else {
+                                Color::WHITE
+                            };
+                            if c != pos.side_to_move() {
+                                return Err(anyhow!(r#"the move "{}" of the wrong side at line {}."#, statement, i + 1));
+                            }
+                            let m = Move::new_from_csa_str(&statement[1..], pos)
+                                .ok_or_else(|| anyhow!(r#"illegal move "{}" at line {}."#, statement, i + 1))?;
+                            let gives_check = pos.gives_check(m);
+                            pos.do_move(m, gives_check);
+                            record.moves.push(CsaMove {
+                                mv: m,
+                                time: None,
+                                comments: vec![],
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if pos.is_none() {
+            // No side to move line. e.g. empty record.
+            record.start_sfen = START_SFEN.to_string();
+        }
+        Ok(record)
+    }
+    pub fn start_position(&self) -> Position {
+        Position::new_from_sfen(&self.start_sfen).unwrap()
+    }
+    // "sfen <start_sfen> moves <move0> <move1> ..."
+    pub fn to_sfen(&self) -> String {
+        let mut s = format!("sfen {} moves", self.start_sfen);
+        for m in self.moves.iter() {
+            s += &format!(" {}", m.mv.to_usi_string());
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csa_record_example() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let buf = std::fs::read("test/example.csa").unwrap();
+                let record = CsaRecord::parse(&buf).unwrap();
+                assert_eq!(record.version, Some("V2.2".to_string()));
+                assert_eq!(record.black_player, Some("NAKAHARA".to_string()));
+                assert_eq!(record.white_player, Some("YONENAGA".to_string()));
+                assert_eq!(
+                    record.information[0],
+                    ("EVENT".to_string(), "13th World Computer Shogi Championship".to_string())
+                );
+                assert_eq!(
+                    record.information[2],
+                    ("START_TIME".to_string(), "2003/05/03 10:30:00".to_string())
+                );
+                assert_eq!(record.start_sfen, START_SFEN);
+                assert_eq!(record.moves.len(), 2);
+                assert_eq!(record.moves[0].mv.to_usi_string(), "2g2f");
+                assert_eq!(record.moves[0].time, Some(std::time::Duration::from_secs(12)));
+                assert_eq!(record.moves[1].mv.to_usi_string(), "3c3d");
+                assert_eq!(record.moves[1].time, Some(std::time::Duration::from_secs(6)));
+                assert_eq!(record.special_move.as_ref().unwrap().name, "%CHUDAN");
+                assert_eq!(record.result, None);
+                assert_eq!(
+                    record.to_sfen(),
+                    "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1 moves 2g2f 3c3d"
+                );
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_csa_record_initial_positions() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                // Handicap by PI.
+                let csa = "V2.2\nPI82HI22KA\n-\n-5142OU,T3\n+7776FU\n%TORYO\n";
+                let record = CsaRecord::parse(csa.as_bytes()).unwrap();
+                assert_eq!(
+                    record.start_sfen,
+                    "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"
+                );
+                assert_eq!(handicap_name(&record.start_sfen), Some("二枚落ち"));
+                assert_eq!(record.start_position().entering_king_points_threshold(Color::WHITE), 17);
+                assert_eq!(record.moves[0].time, Some(std::time::Duration::from_secs(3)));
+                assert_eq!(record.moves[1].time, None);
+                assert_eq!(record.result, Some(GameResult::BlackWin));
+
+                // Board by P1..P9 with hands and "00AL". Trailing spaces are removed.
+                let csa = "P1 *  *  *  *  *  *  * -KE-OU\n\
+                           P2 *  *  *  *  *  *  *  *  *\n\
+                           P3 *  *  *  *  *  *  * +TO *\n\
+                           P4\n\
+                           P5\n\
+                           P6\n\
+                           P7\n\
+                           P8\n\
+                           P9 *  *  *  *  *  *  *  * +OU\n\
+                           P+00KI00KI\n\
+                           P-00AL\n\
+                           +\n\
+                           +0012KI\n\
+                           %TSUMI\n";
+                let record = CsaRecord::parse(csa.as_bytes()).unwrap();
+                assert_eq!(record.start_sfen, "7nk/9/7+P1/9/9/9/9/9/8K b 2G2r2b2g4s3n4l17p 1");
+                assert_eq!(record.moves[0].mv.to_usi_string(), "G*1b");
+                assert_eq!(record.result, Some(GameResult::BlackWin));
+
+                // Pieces by P+ and P-.
+                let csa = "P+59OU00FU\nP-51OU\n+\n";
+                let record = CsaRecord::parse(csa.as_bytes()).unwrap();
+                assert_eq!(record.start_sfen, "4k4/9/9/9/9/9/9/9/4K4 b P 1");
+
+                // Full-width characters are errors.
+                for csa in [
+                    "P+５５FU\nP-51OU\n+\n",
+                    "P+59OU00ＦＵ\nP-51OU\n+\n",
+                    "PI８２HI\n+\n",
+                    "P1 *  * -ＫＥ\n+\n",
+                ] {
+                    assert!(CsaRecord::parse(csa.as_bytes()).is_err(), "{}", csa);
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_csa_record_comments_and_multiple_records() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let csa = "'header\nPI\n+\n'before moves\n+7776FU,T1.5\n'* 30 -3334FU\n-3334FU\n%TORYO,T2\n'resign\n/\nPI\n-\n-3334FU\n";
+                let records = CsaRecord::parse_all(csa.as_bytes()).unwrap();
+                assert_eq!(records.len(), 2);
+                assert_eq!(records[0].comments, vec!["header".to_string(), "before moves".to_string()]);
+                assert_eq!(records[0].moves[0].time, Some(std::time::Duration::from_millis(1500)));
+                assert_eq!(records[0].moves[0].comments, vec!["* 30 -3334FU".to_string()]);
+                let special_move = records[0].special_move.as_ref().unwrap();
+                assert_eq!(special_move.time, Some(std::time::Duration::from_secs(2)));
+                assert_eq!(special_move.comments, vec!["resign".to_string()]);
+                assert_eq!(records[0].result, Some(GameResult::WhiteWin));
+                assert_eq!(records[1].start_sfen, "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1");
+                assert_eq!(records[1].to_sfen().split_whitespace().last(), Some("3c3d"));
+
+                // Illegal moves and moves of the wrong side are errors.
+                assert!(CsaRecord::parse(b"PI\n+\n+7775FU\n").is_err());
+                assert!(CsaRecord::parse(b"PI\n+\n-3334FU\n").is_err());
+                assert!(CsaRecord::parse(b"PI11KA\n+\n").is_err());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
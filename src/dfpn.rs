@@ -0,0 +1,434 @@
+use crate::movegen::*;
+use crate::movetypes::*;
+use crate::position::*;
+use crate::types::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// df-pn (depth-first proof-number search) mate solver.
+// The attacker is the side to move at the root.
+// OR nodes are the positions of the attacker to move and AND nodes are the positions of the defender to move.
+
+const INFINITE: u32 = 100_000_000;
+const MAX_MATE_PLY: usize = 256;
+
+#[derive(Clone, Copy)]
+struct MateEntry {
+    key: Key,
+    attacker: Color, // The same position is an OR node or an AND node by the attacker.
+    pn: u32,
+    dn: u32,
+    mv: Option<Move>, // The move which proves the OR node.
+}
+
+impl MateEntry {
+    const EMPTY: MateEntry = MateEntry {
+        key: Key(0),
+        attacker: Color::BLACK,
+        pn: 1,
+        dn: 1,
+        mv: None,
+    };
+}
+
+// The proof and disproof numbers. The entries are always replaced.
+// Only the results which don't depend on the path are stored, so the table is kept between the searches.
+// The entries of the other attacker are misses.
+pub struct MateTable {
+    entries: Vec<MateEntry>,
+    mega_byte_size: usize,
+}
+
+impl MateTable {
+    pub fn new(mega_byte_size: usize) -> MateTable {
+        let size = mega_byte_size * 1024 * 1024 / std::mem::size_of::<MateEntry>();
+        // The power of 2 for the mask of the index.
+        let size = std::cmp::max(1, size.next_power_of_two() / 2);
+        MateTable {
+            entries: vec![MateEntry::EMPTY; size],
+            mega_byte_size,
+        }
+    }
+    // Nothing is done if the size isn't changed.
+    pub fn resize(&mut self, mega_byte_size: usize) {
+        if self.mega_byte_size != mega_byte_size {
+            *self = MateTable::new(mega_byte_size);
+        }
+    }
+    fn entry(&self, key: Key, attacker: Color) -> &MateEntry {
+        let entry = &self.entries[key.0 as usize & (self.entries.len() - 1)];
+        if entry.key == key && entry.attacker == attacker {
+            entry
+        } else {
+            &MateEntry::EMPTY
+        }
+    }
+    fn store(&mut self, key: Key, attacker: Color, pn: u32, dn: u32, mv: Option<Move>) {
+        let len = self.entries.len();
+        self.entries[key.0 as usize & (len - 1)] = MateEntry {
+            key,
+            attacker,
+            pn,
+            dn,
+            mv,
+        };
+    }
+}
+
+pub enum MateResult {
+    Mate(Vec<Move>),
+    NoMate,
+    Timeout, // Timeout, stopped or unknown because the mate may be longer than MAX_MATE_PLY.
+}
+
+struct Child {
+    mv: Move,
+    pn: u32,
+    dn: u32,
+    path_dependent: bool, // The disproof depends on the path. See DfPn::mid().
+}
+
+// The checks of the attacker. The unpromoted moves of pawns, bishops and rooks are included.
+fn generate_checks(pos: &Position) -> Vec<Move> {
+    let mut mlist = MoveList::new();
+    mlist.generate::<ChecksType>(pos, 0);
+    mlist.slice(0).iter().map(|x| x.mv).filter(|m| pos.legal(*m)).collect()
+}
+
+// The evasions of the defender. Drops of pawns which mate are already excluded as the attacker's moves.
+fn generate_evasions(pos: &Position) -> Vec<Move> {
+    let mut mlist = MoveList::new();
+    mlist.generate::<LegalAllType>(pos, 0);
+    mlist.slice(0).iter().map(|x| x.mv).collect()
+}
+
+pub struct DfPn<'a> {
+    table: &'a mut MateTable,
+    attacker: Color,
+    nodes: u64,
+    stop: Arc<AtomicBool>,
+    timeout: Option<std::time::Duration>,
+    start_time: std::time::Instant,
+    last_info_millis: u128,
+    aborted: bool,
+    max_ply_reached: bool,
+    hide_all_output: bool,
+}
+
+impl<'a> DfPn<'a> {
+    pub fn new(table: &'a mut MateTable, stop: Arc<AtomicBool>, hide_all_output: bool) -> DfPn<'a> {
+        DfPn {
+            table,
+            attacker: Color::BLACK,
+            nodes: 0,
+            stop,
+            timeout: None,
+            start_time: std::time::Instant::now(),
+            last_info_millis: 0,
+            aborted: false,
+            max_ply_reached: false,
+            hide_all_output,
+        }
+    }
+    pub fn solve(&mut self, pos: &mut Position, timeout: Option<std::time::Duration>) -> MateResult {
+        self.nodes = 0;
+        self.timeout = timeout;
+        self.start_time = std::time::Instant::now();
+        self.last_info_millis = 0;
+        self.aborted = false;
+        self.max_ply_reached = false;
+        self.attacker = pos.side_to_move();
+        let (pn, dn, path_dependent) = self.mid(pos, INFINITE, INFINITE, true, 0);
+        if self.aborted {
+            return MateResult::Timeout;
+        }
+        // The disproof may be wrong if it is cut by MAX_MATE_PLY.
+        if dn == 0 && path_dependent && self.max_ply_reached {
+            return MateResult::Timeout;
+        }
+        if pn == 0 {
+            let mut pv = vec![];
+            self.pv(pos, &mut pv);
+            if !self.hide_all_output {
+                println!(
+                    "{} pv {}",
+                    self.info_string(),
+                    pv.iter().map(|m| m.to_usi_string()).collect::<Vec<_>>().join(" ")
+                );
+            }
+            MateResult::Mate(pv)
+        } else {
+            debug_assert!(dn == 0);
+            MateResult::NoMate
+        }
+    }
+    fn info_string(&self) -> String {
+        let elapsed_millis = self.start_time.elapsed().as_millis() + 1; // "+ 1": avoid dividing by 0
+        format!(
+            "info nodes {} nps {} time {}",
+            self.nodes,
+            u128::from(self.nodes) * 1000 / elapsed_millis,
+            elapsed_millis
+        )
+    }
+    fn check_limit(&mut self) {
+        if self.stop.load(Ordering::Relaxed) {
+            self.aborted = true;
+        }
+        let elapsed = self.start_time.elapsed();
+        if let Some(timeout) = self.timeout {
+            if elapsed >= timeout {
+                self.aborted = true;
+            }
+        }
+        if elapsed.as_millis() >= self.last_info_millis + 1000 && !self.hide_all_output {
+            self.last_info_millis = elapsed.as_millis();
+            println!("{}", self.info_string());
+        }
+    }
+    // Search until the proof number or the disproof number reaches its threshold.
+    // The returned values are the proof number, the disproof number and whether the disproof depends on the path.
+    // The disproofs by repetitions or MAX_MATE_PLY depend on the path, so they and the disproofs from them aren't stored.
+    fn mid(&mut self, pos: &mut Position, pn_threshold: u32, dn_threshold: u32, or_node: bool, ply: usize) -> (u32, u32, bool) {
+        self.nodes += 1;
+        if self.nodes % 1024 == 0 {
+            self.check_limit();
+        }
+        if self.aborted {
+            return (1, 1, false);
+        }
+
+        // Both of a draw and a perpetual check are failures of the attacker.
+        match pos.is_repetition() {
+            Repetition::Draw | Repetition::Win | Repetition::Lose => return (INFINITE, 0, true),
+            Repetition::Not | Repetition::Superior | Repetition::Inferior => {}
+        }
+        if ply >= MAX_MATE_PLY {
+            self.max_ply_reached = true;
+            return (INFINITE, 0, true);
+        }
+
+        let key = pos.key();
+        if or_node && !pos.in_check() {
+            if let Some(m) = pos.mate_move_in_1ply() {
+                self.table.store(key, self.attacker, 0, INFINITE, Some(m));
+                return (0, INFINITE, false);
+            }
+        }
+        let moves = if or_node {
+            generate_checks(pos)
+        } else {
+            generate_evasions(pos)
+        };
+        if moves.is_empty() {
+            let (pn, dn) = if or_node { (INFINITE, 0) } else { (0, INFINITE) };
+            self.table.store(key, self.attacker, pn, dn, None);
+            return (pn, dn, false);
+        }
+        let mut children = moves
+            .into_iter()
+            .map(|m| {
+                pos.do_move(m, pos.gives_check(m));
+                let child_key = pos.key();
+                pos.undo_move(m);
+                let entry = self.table.entry(child_key, self.attacker);
+                Child {
+                    mv: m,
+                    pn: entry.pn,
+                    dn: entry.dn,
+                    path_dependent: false,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        loop {
+            // phi is the number minimized at this node (pn at OR nodes, dn at AND nodes) and delta is the other.
+            let phi_delta = |c: &Child| if or_node { (c.pn, c.dn) } else { (c.dn, c.pn) };
+            let mut best_index = 0;
+            let mut phi_1 = INFINITE;
+            let mut phi_2 = INFINITE;
+            let mut delta_sum = 0u32;
+            for (i, c) in children.iter().enumerate() {
+                let (p, d) = phi_delta(c);
+                if p < phi_1 {
+                    best_index = i;
+                    phi_2 = phi_1;
+                    phi_1 = p;
+                } else if p < phi_2 {
+                    phi_2 = p;
+                }
+                // Only a proved or disproved child makes INFINITE. The sum of the unknown numbers is less than it.
+                delta_sum = if delta_sum == INFINITE || d == INFINITE {
+                    INFINITE
+                } else {
+                    std::cmp::min(INFINITE - 1, delta_sum + d)
+                };
+            }
+            let (pn, dn) = if or_node { (phi_1, delta_sum) } else { (delta_sum, phi_1) };
+            if pn >= pn_threshold || dn >= dn_threshold || self.aborted {
+                let mv = if or_node && pn == 0 {
+                    Some(children[best_index].mv)
+                } else {
+                    None
+                };
+                // An OR node is disproved by all the children and an AND node is disproved by any child.
+                let path_dependent = dn == 0
+                    && if or_node {
+                        children.iter().any(|c| c.path_dependent)
+                    } else {
+                        children.iter().filter(|c| c.dn == 0).all(|c| c.path_dependent)
+                    };
+                if !self.aborted && !path_dependent {
+                    self.table.store(key, self.attacker, pn, dn, mv);
+                }
+                return (pn, dn, path_dependent);
+            }
+
+            let (phi_threshold, delta_threshold) = if or_node {
+                (pn_threshold, dn_threshold)
+            } else {
+                (dn_threshold, pn_threshold)
+            };
+            let best = &children[best_index];
+            let (best_phi, best_delta) = phi_delta(best);
+            let child_phi_threshold = std::cmp::min(phi_threshold, phi_2.saturating_add(1));
+            let child_delta_threshold = std::cmp::min(INFINITE, delta_threshold - delta_sum + best_delta);
+            debug_assert!(best_phi < child_phi_threshold);
+            let (child_pn_threshold, child_dn_threshold) = if or_node {
+                (child_phi_threshold, child_delta_threshold)
+            } else {
+                (child_delta_threshold, child_phi_threshold)
+            };
+            let m = best.mv;
+            pos.do_move(m, pos.gives_check(m));
+            let (child_pn, child_dn, child_path_dependent) =
+                self.mid(pos, child_pn_threshold, child_dn_threshold, !or_node, ply + 1);
+            pos.undo_move(m);
+            let best = &mut children[best_index];
+            best.pn = child_pn;
+            best.dn = child_dn;
+            best.path_dependent = child_path_dependent;
+        }
+    }
+    // The proved mate moves. The entries lost by replacement are searched again.
+    fn pv(&mut self, pos: &mut Position, pv: &mut Vec<Move>) {
+        let mut ply = 0;
+        let mut or_node = true;
+        loop {
+            if ply >= MAX_MATE_PLY {
+                break;
+            }
+            let m = if or_node {
+                let mut entry = *self.table.entry(pos.key(), self.attacker);
+                if entry.pn != 0 || entry.mv.is_none() {
+                    self.mid(pos, INFINITE, INFINITE, true, ply);
+                    entry = *self.table.entry(pos.key(), self.attacker);
+                }
+                match entry.mv {
+                    Some(m) if entry.pn == 0 => m,
+                    _ => break,
+                }
+            } else {
+                // Any evasion is mated. The evasion which isn't a drop is preferred to avoid useless interpositions.
+                let evasions = generate_evasions(pos);
+                if evasions.is_empty() {
+                    break;
+                }
+                *evasions.iter().find(|m| !m.is_drop()).unwrap_or(&evasions[0])
+            };
+            pv.push(m);
+            pos.do_move(m, pos.gives_check(m));
+            ply += 1;
+            or_node = !or_node;
+        }
+        for m in pv.iter().rev() {
+            pos.undo_move(*m);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dfpn() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                fn is_mate(sfen: &str, pv: &[Move]) -> bool {
+                    let mut pos = Position::new_from_sfen(sfen).unwrap();
+                    for (i, m) in pv.iter().enumerate() {
+                        if !pos.pseudo_legal::<NotSearchingType>(*m) || !pos.legal(*m) {
+                            return false;
+                        }
+                        let gives_check = pos.gives_check(*m);
+                        if i % 2 == 0 && !gives_check {
+                            return false;
+                        }
+                        pos.do_move(*m, gives_check);
+                    }
+                    pos.in_check() && generate_evasions(&pos).is_empty()
+                }
+                let stop = Arc::new(AtomicBool::new(false));
+                let hide_all_output = true;
+                let mut table = MateTable::new(1);
+                let mut dfpn = DfPn::new(&mut table, stop, hide_all_output);
+                for (sfen, ply) in [("4k4/9/4P4/9/9/9/9/9/4K4 b G 1", 1), ("8l/7k1/9/7P1/9/9/9/9/4K4 b 2G 1", 3)] {
+                    let mut pos = Position::new_from_sfen(sfen).unwrap();
+                    match dfpn.solve(&mut pos, None) {
+                        MateResult::Mate(pv) => {
+                            assert_eq!(pv.len(), ply);
+                            assert!(is_mate(sfen, &pv));
+                        }
+                        _ => panic!("{}", sfen),
+                    }
+                    assert_eq!(pos.to_sfen(), sfen);
+                }
+
+                for sfen in [
+                    "4k4/9/9/9/9/9/9/9/4K4 b - 1", // no check.
+                    "8l/7k1/9/7P1/9/9/9/9/4K4 b G 1",
+                ] {
+                    let mut pos = Position::new_from_sfen(sfen).unwrap();
+                    assert!(matches!(dfpn.solve(&mut pos, None), MateResult::NoMate));
+                    assert_eq!(dfpn.table.entry(pos.key(), pos.side_to_move()).dn, 0);
+                }
+
+                // The disproof by a repetition depends on the path, so it isn't stored.
+                let mut pos = Position::new();
+                pos.reserve_states();
+                for usi_move in ["5i4h", "5a4b", "4h5i", "4b5a"] {
+                    let m = Move::new_from_usi_str(usi_move, &pos).unwrap();
+                    pos.do_move(m, pos.gives_check(m));
+                }
+                assert!(matches!(dfpn.solve(&mut pos, None), MateResult::NoMate));
+                assert_ne!(dfpn.table.entry(pos.key(), pos.side_to_move()).dn, 0);
+
+                // The table is shared by the searches with both colors as the attacker.
+                // The child of the white's mate is searched first as the position of the black attacker.
+                let mut table = MateTable::new(1);
+                for (sfen, ply) in [
+                    ("4k4/9/9/9/9/1p7/1g7/1K7/L8 b g 2", None),
+                    ("4k4/9/9/9/9/1p7/9/1K7/L8 w 2g 1", Some(3)),
+                    ("4k4/9/9/9/9/1p7/1g7/1K7/L8 b g 2", None),
+                    ("4k4/9/9/9/9/9/4P4/4K4/9 b 2G 1", None),
+                    ("4k4/9/4P4/9/9/9/9/9/4K4 b G 1", Some(1)),
+                ] {
+                    let mut dfpn = DfPn::new(&mut table, Arc::new(AtomicBool::new(false)), hide_all_output);
+                    let mut pos = Position::new_from_sfen(sfen).unwrap();
+                    match (dfpn.solve(&mut pos, None), ply) {
+                        (MateResult::Mate(pv), Some(ply)) => {
+                            assert_eq!(pv.len(), ply, "{}", sfen);
+                            assert!(is_mate(sfen, &pv), "{}", sfen);
+                        }
+                        (MateResult::NoMate, None) => {}
+                        _ => panic!("{}", sfen),
+                    }
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -10,6 +10,27 @@ use crate::types::*;
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Hand(pub u32);
 
+// The piece types in hand in the order of CSA and KIF records. Rook first.
+pub const HAND_PIECE_TYPES: [PieceType; 7] = [
+    PieceType::ROOK,
+    PieceType::BISHOP,
+    PieceType::GOLD,
+    PieceType::SILVER,
+    PieceType::KNIGHT,
+    PieceType::LANCE,
+    PieceType::PAWN,
+];
+
+// The number of the pieces of the piece type in a game. pt must be one of HAND_PIECE_TYPES.
+pub fn max_piece_num(pt: PieceType) -> usize {
+    match pt {
+        PieceType::PAWN => 18,
+        PieceType::LANCE | PieceType::KNIGHT | PieceType::SILVER | PieceType::GOLD => 4,
+        PieceType::BISHOP | PieceType::ROOK => 2,
+        _ => unreachable!(),
+    }
+}
+
 impl Hand {
     const PAWN_REQUIRE_BITS: u32 = 5;
     const LANCE_REQUIRE_BITS: u32 = 3;
@@ -300,7 +300,7 @@ impl<'a> BitStreamWriter<'a> {
 }
 
 #[allow(dead_code)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GameResult {
     Draw,
     BlackWin,
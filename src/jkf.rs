@@ -0,0 +1,708 @@
+#[cfg(feature = "kppt")]
+use crate::evaluate::kppt::*;
+use crate::hand::*;
+use crate::kif::*;
+use crate::movetypes::*;
+use crate::position::*;
+use crate::search::*;
+use crate::sfen::*;
+use crate::thread::*;
+use crate::tt::*;
+use crate::types::*;
+use crate::usi::*;
+use crate::usioption::*;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// JSON Kifu Format (JKF) game records.
+// https://github.com/na2hiro/json-kifu-format
+
+// The preset which means the position is written in "data".
+const PRESET_OTHER: &str = "OTHER";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Jkf {
+    #[serde(default)]
+    pub header: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial: Option<JkfInitial>,
+    // moves[0] has only the comments of the start position.
+    pub moves: Vec<JkfMoveFormat>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JkfInitial {
+    pub preset: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<JkfState>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JkfState {
+    pub color: u8, // 0: black, 1: white
+    // board[x - 1][y - 1] is the piece on the square (x, y).
+    pub board: Vec<Vec<JkfPiece>>,
+    // hands[0] is black's hand. e.g. {"FU": 2, "KA": 0, ...}
+    pub hands: Vec<BTreeMap<String, u32>>,
+}
+
+// An empty square is {}.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct JkfPiece {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>, // CSA piece name. e.g. "FU"
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct JkfMoveFormat {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<Vec<String>>,
+    #[serde(rename = "move", skip_serializing_if = "Option::is_none")]
+    pub mv: Option<JkfMove>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<JkfTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub special: Option<String>, // e.g. "TORYO"
+    // The variations which branch off instead of this move.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forks: Option<Vec<Vec<JkfMoveFormat>>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JkfMove {
+    pub color: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<JkfPlace>, // None means a drop move unless "relative" tells otherwise.
+    pub to: JkfPlace,
+    pub piece: String, // The piece before the move.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub same: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promote: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<String>,
+    // KI2 style relative position and movement. e.g. "LU"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct JkfPlace {
+    pub x: u8, // file. 1 to 9.
+    pub y: u8, // rank. 1 to 9.
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JkfTime {
+    pub now: JkfTimeFormat,
+    pub total: JkfTimeFormat,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct JkfTimeFormat {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub h: Option<u32>,
+    pub m: u32,
+    pub s: u32,
+}
+
+impl JkfPlace {
+    fn new(sq: Square) -> JkfPlace {
+        JkfPlace {
+            x: File::new(sq).0 as u8 + 1,
+            y: Rank::new(sq).0 as u8 + 1,
+        }
+    }
+    fn to_square(self) -> Option<Square> {
+        if (1..=9).contains(&self.x) && (1..=9).contains(&self.y) {
+            Some(Square::new(File(self.x as i32 - 1), Rank(self.y as i32 - 1)))
+        } else {
+            None
+        }
+    }
+}
+
+impl JkfState {
+    fn new(pos: &Position) -> JkfState {
+        let board = (0..File::NUM as i32)
+            .map(|file| {
+                (0..Rank::NUM as i32)
+                    .map(|rank| {
+                        let pc = pos.piece_on(Square::new(File(file), Rank(rank)));
+                        if pc == Piece::EMPTY {
+                            JkfPiece::default()
+                        } else {
+                            JkfPiece {
+                                color: Some(Color::new(pc).0 as u8),
+                                kind: Some(PieceType::new(pc).to_csa_str().to_string()),
+                            }
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let hands = [Color::BLACK, Color::WHITE]
+            .iter()
+            .map(|&c| {
+                HAND_PIECE_TYPES
+                    .iter()
+                    .map(|&pt| (pt.to_csa_str().to_string(), pos.hand(c).num(pt)))
+                    .collect()
+            })
+            .collect();
+        JkfState {
+            color: pos.side_to_move().0 as u8,
+            board,
+            hands,
+        }
+    }
+    fn to_sfen(&self) -> Result<String> {
+        if self.board.len() != File::NUM || self.board.iter().any(|column| column.len() != Rank::NUM) {
+            return Err(anyhow!("the board of initial.data must be 9x9."));
+        }
+        let mut sfen = String::new();
+        for rank in 0..Rank::NUM {
+            let mut empty = 0;
+            for file in (0..File::NUM).rev() {
+                let piece = &self.board[file][rank];
+                let kind = match &piece.kind {
+                    Some(kind) => kind,
+                    None => {
+                        empty += 1;
+                        continue;
+                    }
+                };
+                let pt = PieceType::new_from_csa_str(kind)
+                    .ok_or_else(|| anyhow!(r#"invalid piece kind "{}" in initial.data."#, kind))?;
+                let c = parse_color(piece.color.unwrap_or(0))?;
+                if empty != 0 {
+                    sfen += &empty.to_string();
+                    empty = 0;
+                }
+                sfen += Piece::new(c, pt).to_usi_str();
+            }
+            if empty != 0 {
+                sfen += &empty.to_string();
+            }
+            if rank != Rank::NUM - 1 {
+                sfen += "/";
+            }
+        }
+        sfen += if parse_color(self.color)? == Color::BLACK {
+            " b "
+        } else {
+            " w "
+        };
+        let mut hand = String::new();
+        for (i, c) in [Color::BLACK, Color::WHITE].iter().enumerate() {
+            for (kind, &num) in self.hands.get(i).into_iter().flatten() {
+                let pt = PieceType::new_from_csa_str(kind)
+                    .filter(|pt| HAND_PIECE_TYPES.contains(pt))
+                    .ok_or_else(|| anyhow!(r#"invalid hand piece kind "{}" in initial.data."#, kind))?;
+                match num {
+                    0 => {}
+                    1 => hand += Piece::new(*c, pt).to_usi_str(),
+                    _ => hand += &format!("{}{}", num, Piece::new(*c, pt).to_usi_str()),
+                }
+            }
+        }
+        sfen += if hand.is_empty() { "-" } else { &hand };
+        sfen += " 1";
+        Ok(sfen)
+    }
+}
+
+fn parse_color(color: u8) -> Result<Color> {
+    match color {
+        0 => Ok(Color::BLACK),
+        1 => Ok(Color::WHITE),
+        _ => Err(anyhow!("invalid color {}. expected: 0 or 1", color)),
+    }
+}
+
+// JKF "relative" to the KI2 words. "H" (打) is handled separately.
+fn relative_to_ki2_words(relative: &str) -> Result<Vec<char>> {
+    relative
+        .chars()
+        .filter(|c| *c != 'H')
+        .map(|c| match c {
+            'L' => Ok('左'),
+            'C' => Ok('直'),
+            'R' => Ok('右'),
+            'U' => Ok('上'),
+            'M' => Ok('寄'),
+            'D' => Ok('引'),
+            _ => Err(anyhow!(r#"invalid relative "{}""#, relative)),
+        })
+        .collect()
+}
+
+fn jkf_move_to_move(jkf_move: &JkfMove, pos: &Position) -> Result<Move> {
+    if parse_color(jkf_move.color)? != pos.side_to_move() {
+        return Err(anyhow!("the color of the move isn't the side to move: {:?}", jkf_move));
+    }
+    let to = jkf_move
+        .to
+        .to_square()
+        .ok_or_else(|| anyhow!("invalid destination: {:?}", jkf_move))?;
+    let pt = PieceType::new_from_csa_str(&jkf_move.piece).ok_or_else(|| anyhow!("invalid piece: {:?}", jkf_move))?;
+    let promotion = jkf_move.promote == Some(true);
+    let m = if let Some(from) = jkf_move.from {
+        let from = from
+            .to_square()
+            .ok_or_else(|| anyhow!("invalid source square: {:?}", jkf_move))?;
+        if PieceType::new(pos.piece_on(from)) != pt {
+            return Err(anyhow!("the piece isn't on the source square: {:?}", jkf_move));
+        }
+        let promote = if promotion { "+" } else { "" };
+        Move::new_from_usi_str(&format!("{}{}{}", from.to_usi_string(), to.to_usi_string(), promote), pos)
+    } else {
+        // Without "from", the move is decided in the same way as KI2.
+        let relative = jkf_move.relative.as_deref().unwrap_or("");
+        let words = relative_to_ki2_words(relative)?;
+        match relative_move_candidates(pos, to, pt, promotion, relative.contains('H'), &words).as_slice() {
+            [m] => Some(*m),
+            [] => None,
+            _ => return Err(anyhow!("ambiguous move: {:?}", jkf_move)),
+        }
+    };
+    m.ok_or_else(|| anyhow!("illegal move: {:?}", jkf_move))
+}
+
+fn move_to_jkf_move(m: Move, pos: &Position, prev_to: Option<Square>) -> JkfMove {
+    let us = pos.side_to_move();
+    let (from, promote) = if m.is_drop() {
+        (None, None)
+    } else {
+        let promotable = pos.piece_on(m.from()).is_promotable()
+            && (Rank::new(m.from()).is_opponent_field(us) || Rank::new(m.to()).is_opponent_field(us));
+        (Some(JkfPlace::new(m.from())), Some(m.is_promotion()).filter(|_| promotable))
+    };
+    let captured = pos.piece_on(m.to());
+    JkfMove {
+        color: us.0 as u8,
+        from,
+        to: JkfPlace::new(m.to()),
+        piece: PieceType::new(m.piece_moved_before_move()).to_csa_str().to_string(),
+        same: Some(true).filter(|_| prev_to == Some(m.to())),
+        promote,
+        capture: Some(captured)
+            .filter(|pc| *pc != Piece::EMPTY)
+            .map(|pc| PieceType::new(pc).to_csa_str().to_string()),
+        relative: None,
+    }
+}
+
+// Do the moves of jkf_moves from pos and push the line and the lines of the forks to lines.
+// pos and line are restored when this returns.
+fn collect_lines(
+    pos: &mut Position,
+    jkf_moves: &[JkfMoveFormat],
+    line: &mut Vec<Move>,
+    lines: &mut Vec<Vec<Move>>,
+) -> Result<()> {
+    let index = lines.len();
+    lines.push(vec![]);
+    let len = line.len();
+    let mut result = Ok(());
+    for jkf_move in jkf_moves {
+        // The forks branch off from the position before this move.
+        for fork in jkf_move.forks.iter().flatten() {
+            result = collect_lines(pos, fork, line, lines);
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_err() || jkf_move.special.is_some() {
+            break;
+        }
+        if let Some(jkf_move) = &jkf_move.mv {
+            match jkf_move_to_move(jkf_move, pos).with_context(|| anyhow!("ply {}", line.len() + 1)) {
+                Ok(m) => {
+                    let gives_check = pos.gives_check(m);
+                    pos.do_move(m, gives_check);
+                    line.push(m);
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+    }
+    lines[index] = line.clone();
+    for m in line.drain(len..).rev() {
+        pos.undo_move(m);
+    }
+    result
+}
+
+impl Jkf {
+    pub fn parse(s: &str) -> Result<Jkf> {
+        serde_json::from_str(s).context("invalid JKF")
+    }
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Jkf> {
+        let s = std::fs::read_to_string(&path).with_context(|| anyhow!("{}", path.as_ref().display()))?;
+        Jkf::parse(&s)
+    }
+    pub fn to_file<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        let s = serde_json::to_string(self)?;
+        std::fs::write(&path, s).with_context(|| anyhow!("{}", path.as_ref().display()))
+    }
+    // The game record of the moves from start.
+    pub fn new_from_moves(start: &Position, moves: &[Move]) -> Jkf {
+        let initial = match handicap_name(&start.to_sfen()) {
+            Some(name) => JkfInitial {
+                preset: handicap_name_to_preset(name).unwrap().to_string(),
+                data: None,
+            },
+            None => JkfInitial {
+                preset: PRESET_OTHER.to_string(),
+                data: Some(JkfState::new(start)),
+            },
+        };
+        let mut pos = Position::new_from_position(start, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+        let mut jkf_moves = vec![JkfMoveFormat::default()];
+        let mut prev_to = None;
+        for &m in moves {
+            jkf_moves.push(JkfMoveFormat {
+                mv: Some(move_to_jkf_move(m, &pos, prev_to)),
+                ..Default::default()
+            });
+            let gives_check = pos.gives_check(m);
+            pos.do_move(m, gives_check);
+            prev_to = Some(m.to());
+        }
+        Jkf {
+            header: BTreeMap::new(),
+            initial: Some(initial),
+            moves: jkf_moves,
+        }
+    }
+    pub fn start_position(&self) -> Result<Position> {
+        let sfen = match &self.initial {
+            None => START_SFEN.to_string(),
+            Some(initial) if initial.preset == PRESET_OTHER => initial
+                .data
+                .as_ref()
+                .ok_or_else(|| anyhow!(r#"the preset "OTHER" needs initial.data."#))?
+                .to_sfen()?,
+            Some(initial) => handicap_preset_to_name(&initial.preset)
+                .and_then(handicap_sfen)
+                .ok_or_else(|| anyhow!(r#"unknown preset "{}""#, initial.preset))?
+                .to_string(),
+        };
+        Position::new_from_sfen(&sfen).map_err(|e| anyhow!("sfen error: {}", e))
+    }
+    // The main line first, and then the variations in order of appearance.
+    // Each variation contains the moves from the start position.
+    pub fn lines(&self) -> Result<Vec<Vec<Move>>> {
+        let mut pos = self.start_position()?;
+        let mut lines = vec![];
+        collect_lines(&mut pos, &self.moves, &mut vec![], &mut lines)?;
+        Ok(lines)
+    }
+    pub fn main_line(&self) -> Result<Vec<Move>> {
+        let mut pos = self.start_position()?;
+        let mut lines = vec![];
+        // The forks are also validated.
+        collect_lines(&mut pos, &self.moves, &mut vec![], &mut lines)?;
+        Ok(lines.swap_remove(0))
+    }
+    // "sfen <sfen> moves <move0> <move1> ..." of the main line.
+    pub fn to_sfen(&self) -> Result<String> {
+        let mut s = format!("sfen {} moves", self.start_position()?.to_sfen());
+        for m in self.main_line()? {
+            s += &format!(" {}", m.to_usi_string());
+        }
+        Ok(s)
+    }
+    // "sfen <sfen> moves <move0> <move1> ..." of each line. The order is the same as lines().
+    pub fn lines_to_sfen(&self) -> Result<Vec<String>> {
+        let start = self.start_position()?.to_sfen();
+        Ok(self
+            .lines()?
+            .iter()
+            .map(|line| {
+                let mut s = format!("sfen {} moves", start);
+                for m in line {
+                    s += &format!(" {}", m.to_usi_string());
+                }
+                s
+            })
+            .collect())
+    }
+    // Add the comment to the position after the ply-th move of the main line. ply 0 is the start position.
+    pub fn add_comment(&mut self, ply: usize, comment: String) -> Result<()> {
+        let index = if ply == 0 {
+            0
+        } else {
+            self.moves
+                .iter()
+                .enumerate()
+                .filter(|(_, jkf_move)| jkf_move.mv.is_some())
+                .nth(ply - 1)
+                .map(|(i, _)| i)
+                .ok_or_else(|| anyhow!("the main line doesn't have the move of ply {}", ply))?
+        };
+        if self.moves.is_empty() {
+            self.moves.push(JkfMoveFormat::default());
+        }
+        self.moves[index].comments.get_or_insert_with(Vec::new).push(comment);
+        Ok(())
+    }
+}
+
+// The comment of a search result. e.g. "score cp 36 pv 7g7f 3c3d"
+// The score is from the point of view of the side to move, the same as USI "info".
+pub fn analysis_comment(score: Value, pv: &[Move]) -> String {
+    let mut s = format!("score {}", score.to_usi());
+    if !pv.is_empty() {
+        s += " pv";
+        for m in pv {
+            s += &format!(" {}", m.to_usi_string());
+        }
+    }
+    s
+}
+
+// Search every position of the main line of the JKF and write the JKF with the results as the comments.
+pub fn analyse_jkf(args: &[&str]) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(
+            "expected: analyse_jkf <input_path> <output_path> <search_depth> found: {}",
+            args.join(" ")
+        ));
+    }
+    let mut jkf = Jkf::from_file(args[0])?;
+    let search_depth = args[2]
+        .parse::<u32>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as search_depth."#, args[2]))?;
+    let start = jkf.start_position()?;
+    let moves = jkf.main_line()?;
+
+    let mut thread_pool = ThreadPool::new();
+    let mut tt = TranspositionTable::new();
+    #[cfg(feature = "kppt")]
+    let mut ehash = EvalHash::new();
+    let mut reductions = Reductions::new();
+    thread_pool.set(
+        1,
+        &mut tt,
+        #[cfg(feature = "kppt")]
+        &mut ehash,
+        &mut reductions,
+    );
+    let mut is_ready = false;
+    let usi_options = {
+        let mut u = UsiOptions::new();
+        [
+            (UsiOptions::THREADS, "1"),
+            (UsiOptions::USI_HASH, "1024"),
+            #[cfg(feature = "kppt")]
+            (UsiOptions::EVAL_HASH, "256"),
+            (UsiOptions::BOOK_ENABLE, "false"),
+        ]
+        .iter()
+        .for_each(|(name, value)| {
+            setoption(
+                &["name", name, "value", value],
+                &mut u,
+                &mut thread_pool,
+                &mut tt,
+                #[cfg(feature = "kppt")]
+                &mut ehash,
+                &mut reductions,
+                &mut is_ready,
+            );
+        });
+        u
+    };
+    let ponder_mode = false;
+    let hide_all_output = true;
+    let mut pos = Position::new_from_position(&start, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+    for ply in 0..=moves.len() {
+        if ply != 0 {
+            let m = moves[ply - 1];
+            let gives_check = pos.gives_check(m);
+            pos.do_move(m, gives_check);
+        }
+        let mut limits = LimitsType::new();
+        limits.depth = Some(search_depth);
+        limits.start_time = Some(std::time::Instant::now());
+        thread_pool.start_thinking(&pos, &mut tt, limits, &usi_options, ponder_mode, hide_all_output);
+        thread_pool.wait_for_search_finished();
+        let comment = match thread_pool.last_best_root_move.lock().unwrap().as_ref() {
+            Some(rm) if Some(rm.pv[0]).is_normal_move() => {
+                let pv = rm
+                    .pv
+                    .iter()
+                    .copied()
+                    .take_while(|m| Some(*m).is_normal_move())
+                    .collect::<Vec<_>>();
+                analysis_comment(rm.score, &pv)
+            }
+            // No legal moves.
+            _ => continue,
+        };
+        jkf.add_comment(ply, comment)?;
+        println!("info string analysed ply {}", ply);
+    }
+    jkf.to_file(args[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jkf_record() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let jkf = r#"{
+                    "header": {"先手": "A", "後手": "B"},
+                    "initial": {"preset": "HIRATE"},
+                    "moves": [
+                        {"comments": ["start"]},
+                        {"move": {"color": 0, "from": {"x": 7, "y": 7}, "to": {"x": 7, "y": 6}, "piece": "FU"}},
+                        {"move": {"color": 1, "from": {"x": 3, "y": 3}, "to": {"x": 3, "y": 4}, "piece": "FU"},
+                         "forks": [
+                            [
+                                {"move": {"color": 1, "to": {"x": 8, "y": 4}, "piece": "FU"}},
+                                {"move": {"color": 0, "to": {"x": 7, "y": 8}, "piece": "KI", "relative": "U"}}
+                            ]
+                         ]},
+                        {"move": {"color": 0, "from": {"x": 8, "y": 8}, "to": {"x": 2, "y": 2}, "piece": "KA", "promote": true, "capture": "KA"},
+                         "time": {"now": {"m": 0, "s": 3}, "total": {"h": 0, "m": 0, "s": 4}}},
+                        {"move": {"color": 1, "to": {"x": 2, "y": 2}, "piece": "GI", "same": true}},
+                        {"move": {"color": 0, "to": {"x": 4, "y": 5}, "piece": "KA", "relative": "H"}},
+                        {"special": "TORYO"}
+                    ]
+                }"#;
+                let jkf = Jkf::parse(jkf).unwrap();
+                assert_eq!(jkf.header.get("先手").map(String::as_str), Some("A"));
+                assert_eq!(
+                    jkf.to_sfen().unwrap(),
+                    format!("sfen {} moves 7g7f 3c3d 8h2b+ 3a2b B*4e", START_SFEN)
+                );
+                let lines = jkf.lines().unwrap();
+                assert_eq!(lines.len(), 2);
+                assert_eq!(
+                    lines[1].iter().map(|m| m.to_usi_string()).collect::<Vec<_>>(),
+                    vec!["7g7f", "8c8d", "6i7h"]
+                );
+                assert_eq!(
+                    jkf.lines_to_sfen().unwrap(),
+                    vec![
+                        format!("sfen {} moves 7g7f 3c3d 8h2b+ 3a2b B*4e", START_SFEN),
+                        format!("sfen {} moves 7g7f 8c8d 6i7h", START_SFEN),
+                    ]
+                );
+
+                // A custom position.
+                let mut board = vec![vec![JkfPiece::default(); 9]; 9];
+                board[4][0] = JkfPiece {
+                    color: Some(1),
+                    kind: Some("OU".to_string()),
+                };
+                board[4][8] = JkfPiece {
+                    color: Some(0),
+                    kind: Some("OU".to_string()),
+                };
+                board[4][2] = JkfPiece {
+                    color: Some(0),
+                    kind: Some("TO".to_string()),
+                };
+                let mut black_hand = BTreeMap::new();
+                black_hand.insert("KI".to_string(), 2);
+                black_hand.insert("FU".to_string(), 0);
+                let jkf = Jkf {
+                    header: BTreeMap::new(),
+                    initial: Some(JkfInitial {
+                        preset: "OTHER".to_string(),
+                        data: Some(JkfState {
+                            color: 0,
+                            board,
+                            hands: vec![black_hand, BTreeMap::new()],
+                        }),
+                    }),
+                    moves: vec![JkfMoveFormat::default()],
+                };
+                assert_eq!(
+                    jkf.start_position().unwrap().to_sfen(),
+                    "4k4/9/4+P4/9/9/9/9/9/4K4 b 2G 1"
+                );
+
+                // Handicap preset. White moves first.
+                let jkf = Jkf::parse(
+                    r#"{"header": {}, "initial": {"preset": "KA"}, "moves": [{}, {"move": {"color": 1, "from": {"x": 2, "y": 2}, "to": {"x": 1, "y": 1}, "piece": "KA"}}]}"#,
+                );
+                assert!(jkf.unwrap().main_line().is_err());
+                let jkf = Jkf::parse(
+                    r#"{"header": {}, "initial": {"preset": "KA"}, "moves": [{}, {"move": {"color": 1, "from": {"x": 3, "y": 3}, "to": {"x": 3, "y": 4}, "piece": "FU"}}]}"#,
+                )
+                .unwrap();
+                assert_eq!(
+                    jkf.to_sfen().unwrap(),
+                    format!("sfen {} moves 3c3d", handicap_sfen("角落ち").unwrap())
+                );
+                assert!(Jkf::parse(r#"{"header": {}, "initial": {"preset": "XX"}, "moves": [{}]}"#)
+                    .unwrap()
+                    .start_position()
+                    .is_err());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_jkf_round_trip() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                for sfen in [
+                    START_SFEN.to_string(),
+                    handicap_sfen("二枚落ち").unwrap().to_string(),
+                    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1".to_string(),
+                ] {
+                    let start = Position::new_from_sfen(&sfen).unwrap();
+                    let mut pos = Position::new_from_sfen(&sfen).unwrap();
+                    let mut moves = vec![];
+                    // Play the first legal moves for a while.
+                    for _ in 0..20 {
+                        let mut mlist = crate::movegen::MoveList::new();
+                        mlist.generate::<crate::movegen::LegalAllType>(&pos, 0);
+                        let m = match mlist.slice(0).iter().map(|ext_move| ext_move.mv).next() {
+                            Some(m) => m,
+                            None => break,
+                        };
+                        let gives_check = pos.gives_check(m);
+                        pos.do_move(m, gives_check);
+                        moves.push(m);
+                    }
+                    let mut jkf = Jkf::new_from_moves(&start, &moves);
+                    jkf.add_comment(1, analysis_comment(Value(100), &moves[1..3])).unwrap();
+                    let jkf = Jkf::parse(&serde_json::to_string(&jkf).unwrap()).unwrap();
+                    assert_eq!(jkf.start_position().unwrap().to_sfen(), start.to_sfen());
+                    assert_eq!(jkf.main_line().unwrap(), moves);
+                    assert_eq!(
+                        jkf.moves[1].comments.as_ref().unwrap()[0],
+                        format!(
+                            "score {} pv {} {}",
+                            Value(100).to_usi(),
+                            moves[1].to_usi_string(),
+                            moves[2].to_usi_string()
+                        )
+                    );
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -0,0 +1,626 @@
+use crate::bod::*;
+use crate::huffman_code::*;
+use crate::movegen::*;
+use crate::movetypes::*;
+use crate::position::*;
+use crate::sfen::*;
+use crate::types::*;
+use anyhow::{anyhow, Context, Result};
+
+// KIF and KI2 game records.
+// The text must be UTF-8 (e.g. ".kifu" and ".ki2u"). Shift_JIS files need to be converted in advance.
+
+const FILE_CHARS: [char; File::NUM] = ['１', '２', '３', '４', '５', '６', '７', '８', '９'];
+const RANK_CHARS: [char; Rank::NUM] = ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+// The longer names first for parsing.
+const PIECE_NAMES: [(&str, PieceType); 19] = [
+    ("成香", PieceType::PRO_LANCE),
+    ("成桂", PieceType::PRO_KNIGHT),
+    ("成銀", PieceType::PRO_SILVER),
+    ("歩", PieceType::PAWN),
+    ("香", PieceType::LANCE),
+    ("桂", PieceType::KNIGHT),
+    ("銀", PieceType::SILVER),
+    ("金", PieceType::GOLD),
+    ("角", PieceType::BISHOP),
+    ("飛", PieceType::ROOK),
+    ("玉", PieceType::KING),
+    ("王", PieceType::KING),
+    ("と", PieceType::PRO_PAWN),
+    ("杏", PieceType::PRO_LANCE),
+    ("圭", PieceType::PRO_KNIGHT),
+    ("全", PieceType::PRO_SILVER),
+    ("馬", PieceType::HORSE),
+    ("龍", PieceType::DRAGON),
+    ("竜", PieceType::DRAGON),
+];
+
+fn piece_type_to_kif_str(pt: PieceType) -> &'static str {
+    match pt {
+        PieceType::PAWN => "歩",
+        PieceType::LANCE => "香",
+        PieceType::KNIGHT => "桂",
+        PieceType::SILVER => "銀",
+        PieceType::GOLD => "金",
+        PieceType::BISHOP => "角",
+        PieceType::ROOK => "飛",
+        PieceType::KING => "玉",
+        PieceType::PRO_PAWN => "と",
+        PieceType::PRO_LANCE => "成香",
+        PieceType::PRO_KNIGHT => "成桂",
+        PieceType::PRO_SILVER => "成銀",
+        PieceType::HORSE => "馬",
+        PieceType::DRAGON => "龍",
+        _ => unreachable!(),
+    }
+}
+
+fn square_to_kif_string(sq: Square) -> String {
+    format!(
+        "{}{}",
+        FILE_CHARS[File::new(sq).0 as usize],
+        RANK_CHARS[Rank::new(sq).0 as usize]
+    )
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KifMove {
+    pub mv: Move,
+    pub time: Option<std::time::Duration>, // consumed time of the move.
+    pub comments: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KifRecord {
+    pub headers: Vec<(String, String)>, // e.g. ("先手", "...")
+    pub start_sfen: String,
+    pub moves: Vec<KifMove>,
+    pub special_move: Option<String>, // e.g. "投了"
+    pub result: Option<GameResult>,
+    pub comments: Vec<String>, // The comments which aren't after any move.
+}
+
+// special is a KIF special move such as "投了". side_to_move is the side to move when the special move appears.
+fn kif_special_move_to_game_result(special: &str, side_to_move: Color) -> Option<GameResult> {
+    let win = |c: Color| {
+        if c == Color::BLACK {
+            GameResult::BlackWin
+        } else {
+            GameResult::WhiteWin
+        }
+    };
+    match special {
+        "投了" | "詰み" | "切れ負け" | "時間切れ" | "反則負け" => Some(win(side_to_move.inverse())),
+        "反則勝ち" | "入玉勝ち" => Some(win(side_to_move)),
+        "千日手" | "持将棋" => Some(GameResult::Draw),
+        _ => None,
+    }
+}
+
+// "７六" or "同　"
+fn parse_destination(s: &str, prev_to: Option<Square>) -> Option<(Square, &str)> {
+    if let Some(rest) = s.strip_prefix('同') {
+        return Some((prev_to?, rest.trim_start_matches(['　', ' '])));
+    }
+    let mut chars = s.chars();
+    let file = chars.next()?;
+    let file = FILE_CHARS
+        .iter()
+        .position(|c| *c == file)
+        .or_else(|| file.to_digit(10).filter(|d| (1..=9).contains(d)).map(|d| d as usize - 1))?;
+    let rank = chars.next()?;
+    let rank = RANK_CHARS.iter().position(|c| *c == rank)?;
+    Some((Square::new(File(file as i32), Rank(rank as i32)), chars.as_str()))
+}
+
+fn parse_piece_type(s: &str) -> Option<(PieceType, &str)> {
+    PIECE_NAMES
+        .iter()
+        .find_map(|(name, pt)| s.strip_prefix(name).map(|rest| (*pt, rest)))
+}
+
+// "成", "不成" or nothing. "生" is the same as "不成".
+fn parse_promotion(s: &str) -> (Option<bool>, &str) {
+    if let Some(rest) = s.strip_prefix("不成").or_else(|| s.strip_prefix('生')) {
+        (Some(false), rest)
+    } else if let Some(rest) = s.strip_prefix('成') {
+        (Some(true), rest)
+    } else {
+        (None, s)
+    }
+}
+
+// "( 0:01/00:00:01)"
+fn parse_kif_time(s: &str) -> Option<std::time::Duration> {
+    let s = s.trim().strip_prefix('(')?;
+    let time = s.split('/').next()?.trim();
+    let (minutes, seconds) = time.split_once(':')?;
+    let minutes = minutes.trim().parse::<u64>().ok()?;
+    let seconds = seconds.trim().parse::<u64>().ok()?;
+    Some(std::time::Duration::from_secs(minutes * 60 + seconds))
+}
+
+// "７六歩(77)", "同　歩(77)", "２二角成(88)", "５五角打"
+// Return the move and the rest of the string.
+fn parse_kif_move<'a>(s: &'a str, pos: &Position, prev_to: Option<Square>) -> Result<(Move, &'a str)> {
+    let (to, rest) = parse_destination(s, prev_to).ok_or_else(|| anyhow!(r#"invalid destination "{}""#, s))?;
+    let (pt, rest) = parse_piece_type(rest).ok_or_else(|| anyhow!(r#"invalid piece "{}""#, s))?;
+    let (promotion, rest) = parse_promotion(rest);
+    let (usi_move, rest) = if let Some(from) = rest.strip_prefix('(') {
+        let mut chars = from.chars();
+        let from = match (chars.next(), chars.next(), chars.next()) {
+            (Some(file), Some(rank), Some(')')) => File::new_from_csa_char(file)
+                .zip(Rank::new_from_csa_char(rank))
+                .map(|(file, rank)| Square::new(file, rank)),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!(r#"invalid source square "{}""#, s))?;
+        if PieceType::new(pos.piece_on(from)) != pt {
+            return Err(anyhow!(r#"the piece isn't on the source square "{}""#, s));
+        }
+        let promote = if promotion == Some(true) { "+" } else { "" };
+        (
+            format!("{}{}{}", from.to_usi_string(), to.to_usi_string(), promote),
+            chars.as_str(),
+        )
+    } else {
+        let rest = rest.strip_prefix('打').unwrap_or(rest);
+        (format!("{}*{}", pt.to_usi_str(), to.to_usi_string()), rest)
+    };
+    let m = Move::new_from_usi_str(&usi_move, pos).ok_or_else(|| anyhow!(r#"illegal move "{}""#, s))?;
+    Ok((m, rest))
+}
+
+// "７六歩", "同歩", "５八金右", "２二角成", "５五角打"
+// The source square is decided by the relative position and movement words of KI2.
+fn parse_ki2_move(s: &str, pos: &Position, prev_to: Option<Square>) -> Result<Move> {
+    let (to, rest) = parse_destination(s, prev_to).ok_or_else(|| anyhow!(r#"invalid destination "{}""#, s))?;
+    let (pt, mut rest) = parse_piece_type(rest).ok_or_else(|| anyhow!(r#"invalid piece "{}""#, s))?;
+    let mut words = vec![];
+    while let Some(c) = rest.chars().next().filter(|c| "右左直上引寄行入".contains(*c)) {
+        words.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    let (promotion, rest) = parse_promotion(rest);
+    let (is_drop, rest) = match rest.strip_prefix('打') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    if !rest.trim().is_empty() {
+        return Err(anyhow!(r#"invalid move "{}""#, s));
+    }
+    match relative_move_candidates(pos, to, pt, promotion == Some(true), is_drop, &words).as_slice() {
+        [m] => Ok(*m),
+        [] => Err(anyhow!(r#"illegal move "{}""#, s)),
+        _ => Err(anyhow!(r#"ambiguous move "{}""#, s)),
+    }
+}
+
+// The legal moves of pt to "to" which match the KI2 relative position and movement words.
+// words are "右", "左", "直", "上", "引", "寄", "行" and "入". The move is unique if one candidate is left.
+pub fn relative_move_candidates(
+    pos: &Position,
+    to: Square,
+    pt: PieceType,
+    promotion: bool,
+    is_drop: bool,
+    words: &[char],
+) -> Vec<Move> {
+    let us = pos.side_to_move();
+    let mut mlist = MoveList::new();
+    mlist.generate::<LegalAllType>(pos, 0);
+    let candidates = mlist
+        .slice(0)
+        .iter()
+        .map(|ext_move| ext_move.mv)
+        .filter(|m| m.to() == to && PieceType::new(m.piece_moved_before_move()) == pt)
+        .filter(|m| m.is_promotion() == promotion)
+        .collect::<Vec<_>>();
+    // A drop move needs "打" if a piece on the board can move to the same square.
+    let drop_only = is_drop || candidates.iter().all(|m| m.is_drop());
+    let candidates = candidates
+        .into_iter()
+        .filter(|m| m.is_drop() == drop_only)
+        .collect::<Vec<_>>();
+    // Relative to the side to move.
+    let forward = |m: &Move| {
+        let d = Rank::new(m.from()).0 - Rank::new(m.to()).0;
+        if us == Color::BLACK {
+            d
+        } else {
+            -d
+        }
+    };
+    let right = |m: &Move| {
+        let file = File::new(m.from()).0;
+        if us == Color::BLACK {
+            -file
+        } else {
+            file
+        }
+    };
+    let mut candidates = candidates;
+    for word in words.iter() {
+        candidates = match word {
+            '上' | '行' | '入' => candidates.into_iter().filter(|m| forward(m) > 0).collect(),
+            '引' => candidates.into_iter().filter(|m| forward(m) < 0).collect(),
+            '寄' => candidates.into_iter().filter(|m| forward(m) == 0).collect(),
+            '直' => candidates
+                .into_iter()
+                .filter(|m| forward(m) > 0 && File::new(m.from()) == File::new(m.to()))
+                .collect(),
+            _ => candidates,
+        };
+    }
+    for word in words.iter() {
+        let extreme = match word {
+            '右' => candidates.iter().map(right).max(),
+            '左' => candidates.iter().map(right).min(),
+            _ => continue,
+        };
+        candidates.retain(|m| Some(right(m)) == extreme);
+    }
+    candidates
+}
+
+// "key：value"
+fn parse_header(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once('：').or_else(|| line.split_once(':'))?;
+    Some((key.trim().to_string(), value.trim().to_string()))
+}
+
+impl KifRecord {
+    fn new_with_headers() -> KifRecord {
+        KifRecord {
+            start_sfen: START_SFEN.to_string(),
+            ..Default::default()
+        }
+    }
+    // The lines of a board diagram are pushed to bod.
+    fn add_header(&mut self, line: &str, bod: &mut String) -> Result<()> {
+        if is_bod_line(line) {
+            *bod += line;
+            *bod += "\n";
+            return Ok(());
+        }
+        if let Some((key, value)) = parse_header(line) {
+            if key == "手合割" {
+                self.start_sfen = handicap_sfen(&value)
+                    .ok_or_else(|| anyhow!(r#"unknown handicap "{}""#, value))?
+                    .to_string();
+            }
+            self.headers.push((key, value));
+        }
+        Ok(())
+    }
+    // The board diagram takes precedence over "手合割".
+    fn start_position(&mut self, bod: &str) -> Result<Position> {
+        if !bod.is_empty() {
+            self.start_sfen = bod_to_sfen(bod).context("invalid board diagram.")?;
+        }
+        Position::new_from_sfen(&self.start_sfen).map_err(|e| anyhow!("invalid start position. {}", e))
+    }
+    fn add_comment(&mut self, comment: &str) {
+        match self.moves.last_mut() {
+            Some(m) => m.comments.push(comment.to_string()),
+            None => self.comments.push(comment.to_string()),
+        }
+    }
+    pub fn parse_kif(kif: &str) -> Result<KifRecord> {
+        let mut record = KifRecord::new_with_headers();
+        let mut pos: Option<Position> = None;
+        let mut bod = String::new();
+        for (i, line) in kif.lines().enumerate() {
+            let line = line.trim_end();
+            let trimmed = line.trim_start();
+            if let Some(comment) = trimmed.strip_prefix('*') {
+                record.add_comment(comment);
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                record.comments.push(comment.to_string());
+                continue;
+            }
+            if trimmed.is_empty() || trimmed.starts_with('&') {
+                continue;
+            }
+            if trimmed.starts_with("変化：") {
+                // Only the main line is read.
+                break;
+            }
+            match &mut pos {
+                None => {
+                    // "手数＝" is a part of a board diagram.
+                    if trimmed.starts_with("手数") && !trimmed.starts_with("手数＝") {
+                        pos = Some(record.start_position(&bod)?);
+                    } else {
+                        record
+                            .add_header(trimmed, &mut bod)
+                            .with_context(|| anyhow!("at line {}.", i + 1))?;
+                    }
+                }
+                Some(pos) => {
+                    if record.special_move.is_some() || !trimmed.starts_with(|c: char| c.is_ascii_digit()) {
+                        continue;
+                    }
+                    let text = trimmed.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start();
+                    let prev_to = record.moves.last().map(|m| m.mv.to());
+                    if parse_destination(text, prev_to).is_none() {
+                        let special = text.split_whitespace().next().unwrap_or("").to_string();
+                        record.result = kif_special_move_to_game_result(&special, pos.side_to_move());
+                        record.special_move = Some(special);
+                        continue;
+                    }
+                    let (m, rest) = parse_kif_move(text, pos, prev_to).with_context(|| anyhow!("at line {}.", i + 1))?;
+                    let gives_check = pos.gives_check(m);
+                    pos.do_move(m, gives_check);
+                    record.moves.push(KifMove {
+                        mv: m,
+                        time: parse_kif_time(rest),
+                        comments: vec![],
+                    });
+                }
+            }
+        }
+        Ok(record)
+    }
+    pub fn parse_ki2(ki2: &str) -> Result<KifRecord> {
+        const MARKERS: [char; 4] = ['▲', '△', '☗', '☖'];
+        let mut record = KifRecord::new_with_headers();
+        let mut pos: Option<Position> = None;
+        let mut bod = String::new();
+        for (i, line) in ki2.lines().enumerate() {
+            let trimmed = line.trim();
+            if let Some(comment) = trimmed.strip_prefix('*') {
+                record.add_comment(comment);
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                record.comments.push(comment.to_string());
+                continue;
+            }
+            if trimmed.is_empty() || trimmed.starts_with('&') {
+                continue;
+            }
+            if trimmed.starts_with("変化：") {
+                break;
+            }
+            if trimmed.starts_with("まで") {
+                record.result = if trimmed.contains("先手の勝ち") || trimmed.contains("下手の勝ち") {
+                    Some(GameResult::BlackWin)
+                } else if trimmed.contains("後手の勝ち") || trimmed.contains("上手の勝ち") {
+                    Some(GameResult::WhiteWin)
+                } else if trimmed.contains("千日手") || trimmed.contains("持将棋") {
+                    Some(GameResult::Draw)
+                } else {
+                    None
+                };
+                continue;
+            }
+            if !trimmed.starts_with(MARKERS) {
+                if pos.is_none() {
+                    record
+                        .add_header(trimmed, &mut bod)
+                        .with_context(|| anyhow!("at line {}.", i + 1))?;
+                }
+                continue;
+            }
+            let pos = match &mut pos {
+                Some(pos) => pos,
+                None => pos.insert(record.start_position(&bod)?),
+            };
+            for text in trimmed.split(MARKERS).map(|s| s.trim()).filter(|s| !s.is_empty()) {
+                let prev_to = record.moves.last().map(|m| m.mv.to());
+                let m = parse_ki2_move(text, pos, prev_to).with_context(|| anyhow!("at line {}.", i + 1))?;
+                let gives_check = pos.gives_check(m);
+                pos.do_move(m, gives_check);
+                record.moves.push(KifMove {
+                    mv: m,
+                    time: None,
+                    comments: vec![],
+                });
+            }
+        }
+        Ok(record)
+    }
+    // "sfen <start_sfen> moves <move0> <move1> ..."
+    pub fn to_sfen(&self) -> String {
+        let mut s = format!("sfen {} moves", self.start_sfen);
+        for m in self.moves.iter() {
+            s += &format!(" {}", m.mv.to_usi_string());
+        }
+        s
+    }
+}
+
+// The move in KIF notation. e.g. "７六歩(77)", "同　歩(23)", "２二角成(88)", "５五角打"
+// prev_to is the destination of the previous move.
+pub fn move_to_kif_string(m: Move, pos: &Position, prev_to: Option<Square>) -> String {
+    let mut s = if prev_to == Some(m.to()) {
+        "同　".to_string()
+    } else {
+        square_to_kif_string(m.to())
+    };
+    if m.is_drop() {
+        s += piece_type_to_kif_str(m.piece_type_dropped());
+        s += "打";
+    } else {
+        let pc = pos.piece_on(m.from());
+        s += piece_type_to_kif_str(PieceType::new(pc));
+        if m.is_promotion() {
+            s += "成";
+        } else if pc.is_promotable()
+            && (Rank::new(m.from()).is_opponent_field(pos.side_to_move())
+                || Rank::new(m.to()).is_opponent_field(pos.side_to_move()))
+        {
+            s += "不成";
+        }
+        s += &format!("({}{})", File::new(m.from()).to_csa_char(), Rank::new(m.from()).to_csa_char());
+    }
+    s
+}
+
+// KIF game record of the moves from start.
+// The start position is written as "手合割" if possible, otherwise as a board diagram.
+pub fn moves_to_kif_string(start: &Position, moves: &[Move]) -> String {
+    let mut s = match handicap_name(&start.to_sfen()) {
+        Some(handicap) => format!("手合割：{}\n", handicap),
+        None => start.to_bod_string(),
+    };
+    s += "手数----指手---------消費時間--\n";
+    let mut pos = Position::new_from_position(start, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+    let mut prev_to = None;
+    for (i, m) in moves.iter().enumerate() {
+        s += &format!("{:>4} {}\n", i + 1, move_to_kif_string(*m, &pos, prev_to));
+        let gives_check = pos.gives_check(*m);
+        pos.do_move(*m, gives_check);
+        prev_to = Some(m.to());
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kif_record() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let kif = "# ---- comment\n\
+                           開始日時：2020/01/01 10:00:00\n\
+                           手合割：平手\n\
+                           先手：A\n\
+                           後手：B\n\
+                           手数----指手---------消費時間--\n\
+                           \x20  1 ７六歩(77)   ( 0:01/00:00:01)\n\
+                           *opening\n\
+                           \x20  2 ３四歩(33)   ( 1:02/00:01:02)\n\
+                           \x20  3 ２二角成(88) ( 0:03/00:00:04)\n\
+                           \x20  4 同　銀(31)   ( 0:04/00:01:06)\n\
+                           \x20  5 ５八金(49)   ( 0:05/00:00:09)\n\
+                           \x20  6 ４五角打     ( 0:06/00:01:12)\n\
+                           \x20  7 投了         ( 0:07/00:00:16)\n\
+                           まで6手で後手の勝ち\n";
+                let record = KifRecord::parse_kif(kif).unwrap();
+                assert_eq!(record.headers[0], ("開始日時".to_string(), "2020/01/01 10:00:00".to_string()));
+                assert_eq!(record.headers[2], ("先手".to_string(), "A".to_string()));
+                assert_eq!(
+                    record.to_sfen(),
+                    format!("sfen {} moves 7g7f 3c3d 8h2b+ 3a2b 4i5h B*4e", START_SFEN)
+                );
+                assert_eq!(record.moves[0].time, Some(std::time::Duration::from_secs(1)));
+                assert_eq!(record.moves[1].time, Some(std::time::Duration::from_secs(62)));
+                assert_eq!(record.moves[0].comments, vec!["opening".to_string()]);
+                assert_eq!(record.special_move, Some("投了".to_string()));
+                assert_eq!(record.result, Some(GameResult::WhiteWin));
+
+                let start = Position::new();
+                let moves = record.moves.iter().map(|m| m.mv).collect::<Vec<_>>();
+                let written = moves_to_kif_string(&start, &moves);
+                assert_eq!(
+                    written,
+                    "手合割：平手\n\
+                     手数----指手---------消費時間--\n\
+                     \x20  1 ７六歩(77)\n\
+                     \x20  2 ３四歩(33)\n\
+                     \x20  3 ２二角成(88)\n\
+                     \x20  4 同　銀(31)\n\
+                     \x20  5 ５八金(49)\n\
+                     \x20  6 ４五角打\n"
+                );
+                let reread = KifRecord::parse_kif(&written).unwrap();
+                assert_eq!(reread.moves.iter().map(|m| m.mv).collect::<Vec<_>>(), moves);
+
+                // Handicap.
+                let kif = "手合割：角落ち\n手数----指手---------消費時間--\n   1 ３四歩(33)\n   2 ７六歩(77)\n";
+                let record = KifRecord::parse_kif(kif).unwrap();
+                assert_eq!(
+                    record.to_sfen(),
+                    "sfen lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1 moves 3c3d 7g7f"
+                );
+                let start = Position::new_from_sfen(&record.start_sfen).unwrap();
+                assert!(moves_to_kif_string(&start, &[]).starts_with("手合割：角落ち\n"));
+                assert_eq!(start.entering_king_points_threshold(Color::WHITE), 22);
+
+                // Board diagram.
+                let kif = "後手の持駒：なし\n\
+                           \x20 ９ ８ ７ ６ ５ ４ ３ ２ １\n\
+                           +---------------------------+\n\
+                           | ・ ・ ・ ・ ・ ・ ・v桂v香|一\n\
+                           | ・ ・ ・ ・ ・ ・v玉 ・ ・|二\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|三\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|四\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|五\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|六\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|七\n\
+                           | ・ ・ ・ ・ ・ ・ ・ ・ ・|八\n\
+                           | ・ ・ ・ ・ 玉 ・ ・ ・ ・|九\n\
+                           +---------------------------+\n\
+                           先手の持駒：金\n\
+                           手数----指手---------消費時間--\n\
+                           \x20  1 ３三金打\n";
+                let record = KifRecord::parse_kif(kif).unwrap();
+                assert_eq!(record.to_sfen(), "sfen 7nl/6k2/9/9/9/9/9/9/4K4 b G 1 moves G*3c");
+                let start = Position::new_from_sfen(&record.start_sfen).unwrap();
+                let moves = record.moves.iter().map(|m| m.mv).collect::<Vec<_>>();
+                assert_eq!(moves_to_kif_string(&start, &moves), kif);
+
+                // Illegal moves are errors.
+                assert!(KifRecord::parse_kif("手数----指手---------消費時間--\n   1 ７五歩(77)\n").is_err());
+                assert!(KifRecord::parse_kif("手数----指手---------消費時間--\n   1 ７六銀(77)\n").is_err());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_ki2_record() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let ki2 = "先手：A\n\
+                           後手：B\n\
+                           \n\
+                           ▲７六歩    △３四歩    ▲２二角成  △同　銀\n\
+                           *comment\n\
+                           ▲５八金右  △４五角打  ▲７八金    △５二金右\n\
+                           ▲４八銀    △６七角成\n\
+                           まで10手で後手の勝ち\n";
+                let record = KifRecord::parse_ki2(ki2).unwrap();
+                assert_eq!(
+                    record.to_sfen(),
+                    format!(
+                        "sfen {} moves 7g7f 3c3d 8h2b+ 3a2b 4i5h B*4e 6i7h 6a5b 3i4h 4e6g+",
+                        START_SFEN
+                    )
+                );
+                assert_eq!(record.moves[3].comments, vec!["comment".to_string()]);
+                assert_eq!(record.result, Some(GameResult::WhiteWin));
+
+                // Ambiguous and illegal moves are errors.
+                assert!(KifRecord::parse_ki2("▲５八金\n").is_err());
+                assert!(KifRecord::parse_ki2("▲５八金直\n").is_err());
+                assert!(KifRecord::parse_ki2("▲５八金左\n").is_ok());
+                assert!(KifRecord::parse_ki2("▲７六歩成\n").is_err());
+
+                // A drop move without "打" when no piece on the board can move there.
+                let record = KifRecord::parse_ki2("▲７六歩 △３四歩 ▲２二角成 △同銀 ▲４五角\n").unwrap();
+                assert_eq!(record.moves[4].mv.to_usi_string(), "B*4e");
+
+                // 引, 右, 左 and 寄.
+                let sfen = "4k4/9/9/9/9/4G4/9/3G1G3/4K4 b - 1";
+                let pos = Position::new_from_sfen(sfen).unwrap();
+                assert_eq!(parse_ki2_move("５七金引", &pos, None).unwrap().to_usi_string(), "5f5g");
+                assert_eq!(parse_ki2_move("５八金右", &pos, None).unwrap().to_usi_string(), "4h5h");
+                assert_eq!(parse_ki2_move("５八金左", &pos, None).unwrap().to_usi_string(), "6h5h");
+                assert!(parse_ki2_move("５八金寄", &pos, None).is_err());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -91,12 +91,24 @@ fn random_move(pos: &mut Position, rng: &mut ThreadRng) {
     }
 }
 
+// The game ply beyond which the game from the root position of start_ply is a draw. 0 means no limit.
+// max_moves_to_draw is counted from the root position, so the roots of any game ply get the same number of moves.
+fn max_moves_to_draw_ply(start_ply: i32, max_moves_to_draw: i32) -> i32 {
+    if max_moves_to_draw == 0 {
+        0
+    } else {
+        (start_ply - 1).saturating_add(max_moves_to_draw)
+    }
+}
+
 pub fn generate_teachers(args: &[&str]) -> Result<()> {
-    if args.len() != 5 {
+    if args.len() != 5 && args.len() != 6 {
         const S: &str = concat!(
             "invalid generate_teachers command.\n",
             "expected:\n",
-            r#"generate_teachers <output_file_path> <root_positions_file_path> <search_depth> <num_threads> <num_teachers>"#,
+            r#"generate_teachers <output_file_path> <root_positions_file_path> <search_depth> <num_threads> <num_teachers> [max_moves_to_draw]"#,
+            "\n",
+            "max_moves_to_draw is the number of moves from each root position. The default is 400. 0 means no limit.",
         );
         return Err(anyhow!(S));
     }
@@ -105,6 +117,8 @@ pub fn generate_teachers(args: &[&str]) -> Result<()> {
     let search_depth = args[2];
     let num_threads = args[3];
     let num_teachers = args[4];
+    // The games are long without the limit.
+    let max_moves_to_draw = args.get(5).copied().unwrap_or("400");
     let writer = std::sync::Arc::new(std::sync::Mutex::new(
         TeacherWriter::new(output).with_context(|| anyhow!(r#"cannot create file "{}"."#, output))?,
     ));
@@ -120,6 +134,10 @@ pub fn generate_teachers(args: &[&str]) -> Result<()> {
     let num_teachers = num_teachers
         .parse::<usize>()
         .with_context(|| anyhow!(r#"cannot parse "{}" as num_teachers."#, num_teachers))?;
+    let max_moves_to_draw = max_moves_to_draw
+        .parse::<u32>()
+        .with_context(|| anyhow!(r#"cannot parse "{}" as max_moves_to_draw."#, max_moves_to_draw))?;
+    let max_moves_to_draw = i32::try_from(max_moves_to_draw).unwrap_or(i32::MAX);
     let count_teachers = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
     let mut v = vec![];
     for _ in 0..num_threads {
@@ -141,7 +159,7 @@ pub fn generate_teachers(args: &[&str]) -> Result<()> {
                 &mut reductions,
             );
             let mut is_ready = false;
-            let usi_options = {
+            let mut usi_options = {
                 let mut u = UsiOptions::new();
                 [
                     (UsiOptions::MULTI_PV, "1"),
@@ -174,7 +192,6 @@ pub fn generate_teachers(args: &[&str]) -> Result<()> {
             };
             let ponder_mode = false;
             let hide_all_output = true;
-            const MAX_MOVES: i32 = 400;
             let mut hcpes: Vec<HuffmanCodedPositionAndEval> = vec![];
             'game_start: while count_teachers.load(std::sync::atomic::Ordering::Relaxed) < num_teachers {
                 hcpes.clear();
@@ -183,11 +200,28 @@ pub fn generate_teachers(args: &[&str]) -> Result<()> {
                 random_move(&mut pos, &mut rng);
                 let mut position_key_appearances = std::collections::HashMap::new();
                 let start_ply = pos.ply();
-                let max_moves_ply = start_ply + MAX_MOVES;
+                // The search uses the same limit.
+                setoption(
+                    &[
+                        "name",
+                        UsiOptions::MAX_MOVES_TO_DRAW,
+                        "value",
+                        &max_moves_to_draw_ply(start_ply, max_moves_to_draw).to_string(),
+                    ],
+                    &mut usi_options,
+                    &mut thread_pool,
+                    &mut tt,
+                    #[cfg(feature = "kppt")]
+                    &mut ehash,
+                    &mut reductions,
+                    &mut is_ready,
+                );
+                let max_moves_to_draw_ply = usi_options.get_i64(UsiOptions::MAX_MOVES_TO_DRAW) as i32;
                 let game_result;
                 let end_ply;
                 loop {
-                    if pos.ply() >= max_moves_ply {
+                    // The same rule as the search. The positions beyond max_moves_to_draw_ply are draws.
+                    if max_moves_to_draw_ply != 0 && pos.ply() > max_moves_to_draw_ply {
                         game_result = GameResult::Draw;
                         end_ply = pos.ply() as i16;
                         hcpes.iter_mut().for_each(|hcpe| {
@@ -264,3 +298,18 @@ pub fn generate_teachers(args: &[&str]) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_moves_to_draw_ply() {
+        assert_eq!(max_moves_to_draw_ply(1, 0), 0);
+        assert_eq!(max_moves_to_draw_ply(1, 400), 400);
+        // The root of a large game ply gets the same number of moves.
+        assert_eq!(max_moves_to_draw_ply(300, 400), 699);
+        assert_eq!(max_moves_to_draw_ply(500, 400), 899);
+        assert_eq!(max_moves_to_draw_ply(500, i32::MAX), i32::MAX);
+    }
+}
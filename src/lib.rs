@@ -14,12 +14,17 @@ extern crate enum_derive;
 extern crate static_assertions;
 mod authors;
 mod bitboard;
+mod bod;
 mod book;
+mod csa;
+mod dfpn;
 mod engine_name;
 mod evaluate;
 mod file_to_vec;
 mod hand;
 mod huffman_code;
+mod jkf;
+mod kif;
 mod learn;
 mod movegen;
 mod movepick;
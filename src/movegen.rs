@@ -10,6 +10,7 @@ pub trait AllowMovesTrait {
     const LEGALS: bool;
     const ALL: bool;
     const ALLOW_PSEUDO_LEGAL: bool;
+    const CHECKS: bool;
 }
 
 pub struct CaptureOrPawnPromotionsType;
@@ -20,6 +21,8 @@ pub struct NonEvasionsType;
 pub struct NonEvasionsAllType;
 pub struct LegalType;
 pub struct LegalAllType;
+pub struct ChecksType;
+pub struct QuietChecksType;
 
 impl AllowMovesTrait for CaptureOrPawnPromotionsType {
     const ALLOW_CAPTURES: bool = true;
@@ -28,6 +31,7 @@ impl AllowMovesTrait for CaptureOrPawnPromotionsType {
     const LEGALS: bool = false;
     const ALL: bool = false;
     const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = false;
 }
 impl AllowMovesTrait for QuietsWithoutPawnPromotionsType {
     const ALLOW_CAPTURES: bool = false;
@@ -36,6 +40,7 @@ impl AllowMovesTrait for QuietsWithoutPawnPromotionsType {
     const LEGALS: bool = false;
     const ALL: bool = false;
     const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = false;
 }
 impl AllowMovesTrait for EvasionsType {
     const ALLOW_CAPTURES: bool = true;
@@ -44,6 +49,7 @@ impl AllowMovesTrait for EvasionsType {
     const LEGALS: bool = false;
     const ALL: bool = false;
     const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = false;
 }
 impl AllowMovesTrait for EvasionsAllType {
     const ALLOW_CAPTURES: bool = true;
@@ -52,6 +58,7 @@ impl AllowMovesTrait for EvasionsAllType {
     const LEGALS: bool = false;
     const ALL: bool = true;
     const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = false;
 }
 impl AllowMovesTrait for NonEvasionsType {
     const ALLOW_CAPTURES: bool = true;
@@ -60,6 +67,7 @@ impl AllowMovesTrait for NonEvasionsType {
     const LEGALS: bool = false;
     const ALL: bool = false;
     const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = false;
 }
 impl AllowMovesTrait for NonEvasionsAllType {
     const ALLOW_CAPTURES: bool = true;
@@ -68,6 +76,7 @@ impl AllowMovesTrait for NonEvasionsAllType {
     const LEGALS: bool = false;
     const ALL: bool = true;
     const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = false;
 }
 impl AllowMovesTrait for LegalType {
     const ALLOW_CAPTURES: bool = true;
@@ -76,6 +85,7 @@ impl AllowMovesTrait for LegalType {
     const LEGALS: bool = true;
     const ALL: bool = false;
     const ALLOW_PSEUDO_LEGAL: bool = false;
+    const CHECKS: bool = false;
 }
 impl AllowMovesTrait for LegalAllType {
     const ALLOW_CAPTURES: bool = true;
@@ -84,6 +94,25 @@ impl AllowMovesTrait for LegalAllType {
     const LEGALS: bool = true;
     const ALL: bool = true;
     const ALLOW_PSEUDO_LEGAL: bool = false;
+    const CHECKS: bool = false;
+}
+impl AllowMovesTrait for ChecksType {
+    const ALLOW_CAPTURES: bool = true;
+    const ALLOW_QUIETS: bool = true;
+    const EVASIONS: bool = false;
+    const LEGALS: bool = false;
+    const ALL: bool = true;
+    const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = true;
+}
+impl AllowMovesTrait for QuietChecksType {
+    const ALLOW_CAPTURES: bool = false;
+    const ALLOW_QUIETS: bool = true;
+    const EVASIONS: bool = false;
+    const LEGALS: bool = false;
+    const ALL: bool = true;
+    const ALLOW_PSEUDO_LEGAL: bool = true;
+    const CHECKS: bool = true;
 }
 
 pub struct MoveList {
@@ -700,6 +729,115 @@ impl MoveList {
             }
         }
     }
+    // Checks including discovered checks and drops. Unpromoted moves are included like NonEvasionsAllType.
+    // The moves are pseudo legal.
+    fn generate_checks<AMT: AllowMovesTrait>(&mut self, pos: &Position, current_size: usize) {
+        debug_assert!(AMT::CHECKS);
+        if pos.in_check() {
+            // The evasions are few, so we filter them.
+            self.generate_evasions::<True>(pos, current_size);
+            let mut i = current_size;
+            while i != self.size {
+                let m = unsafe { (*self.ext_moves[i].as_ptr()).mv };
+                if pos.gives_check(m) && (AMT::ALLOW_CAPTURES || !m.is_capture(pos)) {
+                    i += 1;
+                } else {
+                    self.size -= 1;
+                    unsafe {
+                        (*self.ext_moves[i].as_mut_ptr()).mv = (*self.ext_moves[self.size].as_ptr()).mv;
+                    }
+                }
+            }
+            return;
+        }
+
+        self.size = current_size;
+        let us = pos.side_to_move();
+        let them = us.inverse();
+        let ksq = pos.king_square(them);
+        let target = if AMT::ALLOW_CAPTURES {
+            !pos.pieces_c(us)
+        } else {
+            pos.empty_bb()
+        };
+        let discovered_check_candidates = pos.blockers_for_king(them) & pos.pieces_c(us);
+        let rank1 = Rank::new_from_color_and_rank_as_black(us, RankAsBlack::RANK1);
+        for from in pos.pieces_c(us) {
+            let pc = pos.piece_on(from);
+            let pt = PieceType::new(pc);
+            let direct_check_candidate = match pt {
+                // sliders can check from a distance.
+                PieceType::LANCE | PieceType::BISHOP | PieceType::ROOK | PieceType::HORSE | PieceType::DRAGON => true,
+                PieceType::KING => false,
+                _ => Bitboard::proximity_check_mask(pc, ksq).is_set(from),
+            };
+            if !direct_check_candidate && !discovered_check_candidates.is_set(from) {
+                continue;
+            }
+            let from_is_opponent_field = Rank::new(from).is_opponent_field(us);
+            let to_bb = ATTACK_TABLE.attack(pt, us, from, &pos.occupied_bb()) & target;
+            for to in to_bb {
+                let rank_to = Rank::new(to);
+                let promotable = pt.is_promotable() && (from_is_opponent_field || rank_to.is_opponent_field(us));
+                let unpromotable = match pt {
+                    PieceType::PAWN | PieceType::LANCE => rank_to != rank1,
+                    PieceType::KNIGHT => !rank_to.is_in_front_of(us, RankAsBlack::RANK3),
+                    _ => true,
+                };
+                if promotable {
+                    let m = Move::new_promote(from, to, pc);
+                    if pos.gives_check(m) {
+                        self.push(m);
+                    }
+                }
+                if unpromotable {
+                    let m = Move::new_unpromote(from, to, pc);
+                    if pos.gives_check(m) {
+                        self.push(m);
+                    }
+                }
+            }
+        }
+
+        let hand = pos.hand(us);
+        for &pt in &[
+            PieceType::PAWN,
+            PieceType::LANCE,
+            PieceType::KNIGHT,
+            PieceType::SILVER,
+            PieceType::GOLD,
+            PieceType::BISHOP,
+            PieceType::ROOK,
+        ] {
+            if !hand.exist(pt) {
+                continue;
+            }
+            let mut to_bb = pos.check_squares(pt) & pos.empty_bb();
+            match pt {
+                PieceType::PAWN => {
+                    // avoid two pawns.
+                    to_bb &= !Bitboard::rank_mask(rank1);
+                    for pawn_sq in pos.pieces_cp(us, PieceType::PAWN) {
+                        to_bb &= !Bitboard::file_mask(File::new(pawn_sq));
+                    }
+                }
+                PieceType::LANCE => to_bb &= !Bitboard::rank_mask(rank1),
+                PieceType::KNIGHT => {
+                    let rank2 = Rank::new_from_color_and_rank_as_black(us, RankAsBlack::RANK2);
+                    to_bb &= !(Bitboard::rank_mask(rank1) | Bitboard::rank_mask(rank2));
+                }
+                _ => {}
+            }
+            let pc = Piece::new(us, pt);
+            for to in to_bb {
+                // avoid drop pawn mate.
+                if pt == PieceType::PAWN && pos.is_drop_pawn_mate(us, to) {
+                    continue;
+                }
+                self.push(Move::new_drop(pc, to));
+            }
+        }
+    }
     fn generate_legals<ISALL: Bool>(&mut self, pos: &Position, current_size: usize) {
         if pos.in_check() {
             if ISALL::BOOL {
@@ -733,6 +871,8 @@ impl MoveList {
             } else {
                 self.generate_legals::<False>(pos, current_size);
             }
+        } else if AMT::CHECKS {
+            self.generate_checks::<AMT>(pos, current_size);
         } else if AMT::EVASIONS {
             if AMT::ALL {
                 self.generate_evasions::<True>(pos, current_size);
@@ -1182,6 +1322,77 @@ mod tests {
         assert_eq!(mlist.size, 197);
     }
 
+    #[test]
+    fn test_generate_checks() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                // Compare the checks with LegalAllType moves which give check at all the nodes like perft.
+                fn perft_checks(pos: &mut Position, depth: u32) -> u64 {
+                    let mut legals = MoveList::new();
+                    legals.generate::<LegalAllType>(pos, 0);
+                    let legals = legals.slice(0).iter().map(|x| x.mv).collect::<Vec<_>>();
+                    let mut cnt = 0;
+                    for quiet in [false, true] {
+                        let mut expected = legals
+                            .iter()
+                            .filter(|&&m| pos.gives_check(m) && (!quiet || !m.is_capture(pos)))
+                            .map(|m| m.0.get())
+                            .collect::<Vec<_>>();
+                        let mut mlist = MoveList::new();
+                        if quiet {
+                            mlist.generate::<QuietChecksType>(pos, 0);
+                        } else {
+                            mlist.generate::<ChecksType>(pos, 0);
+                        }
+                        let mut checks = mlist
+                            .slice(0)
+                            .iter()
+                            .map(|x| x.mv)
+                            .filter(|&m| pos.legal(m))
+                            .map(|m| m.0.get())
+                            .collect::<Vec<_>>();
+                        expected.sort_unstable();
+                        checks.sort_unstable();
+                        assert_eq!(checks, expected, "{}", pos.to_sfen());
+                        if !quiet {
+                            cnt += checks.len() as u64;
+                        }
+                    }
+                    if depth > 1 {
+                        for m in legals {
+                            pos.do_move(m, pos.gives_check(m));
+                            cnt += perft_checks(pos, depth - 1);
+                            pos.undo_move(m);
+                        }
+                    }
+                    cnt
+                }
+                for (sfen, depth, expected) in [
+                    ("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1", 3, 48),
+                    (
+                        "l6nl/5+P1gk/2np1S3/p1p4Pp/3P2Sp1/1PPb2P1P/P5GS1/R8/LN4bKL w GR5pnsg 1",
+                        2,
+                        804,
+                    ),
+                    (
+                        "ln3G2l/7k1/3pgsn2/2p2bpp1/p4p3/3sSbn1P/P2P1GPP1/2+r3S1K/L3RG1NL w P6p 106",
+                        2,
+                        8,
+                    ),
+                    ("4k4/9/6G2/9/4S3B/9/9/9/4L3K b 2PN 1", 2, 13), // discovered checks.
+                    ("9/4k4/r8/3b5/4L4/9/9/9/4K4 w pnsg 1", 2, 26), // in check.
+                    ("kl7/1n7/K8/9/9/9/9/9/9 b P 1", 2, 4),         // drop pawn mate.
+                ] {
+                    let mut pos = Position::new_from_sfen(sfen).unwrap();
+                    assert_eq!(perft_checks(&mut pos, depth), expected, "{}", sfen);
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
     #[test]
     fn test_move_new_from_csa_str() {
         let sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
@@ -233,7 +233,7 @@ custom_derive! {
 custom_derive! {
     #[derive(Debug, NextVariant)]
     enum StagesForQSearch {
-        QSearchTt, QCaptureInit, QCapture, /*QCheckInit, QCheck,*/
+        QSearchTt, QCaptureInit, QCapture, QCheckInit, QCheck,
         QRecaptureTt, QRecaptureInit, QRecapture,
         EvasionTt, EvasionInit, Evasion,
     }
@@ -313,6 +313,22 @@ fn select_next_quiet(
     None
 }
 
+// The pawn promotions which give check are already picked as captures.
+// The unpromoted moves which the search doesn't use (e.g. a pawn to the opponent field) are skipped.
+fn select_next_quiet_check(list: &[ExtMove], current_index: &mut usize, pos: &Position, tt_move: Option<Move>) -> Option<Move> {
+    for ext_move in list {
+        *current_index += 1;
+        let m = ext_move.mv;
+        if m != tt_move.non_zero_unwrap_unchecked()
+            && !m.is_capture_or_pawn_promotion(pos)
+            && pos.pseudo_legal::<SearchingType>(m)
+        {
+            return Some(m);
+        }
+    }
+    None
+}
+
 fn select_next_bad_capture(list: &[ExtMove], current_index: &mut usize, tt_move: Option<Move>) -> Option<Move> {
     for ext_move in list {
         *current_index += 1;
@@ -593,6 +609,7 @@ pub struct MovePickerForQSearch<'a> {
     recapture_square: Square,
     stage: StagesForQSearch,
     tt_move: Option<Move>,
+    depth: Depth,
     move_list: MoveList,
 }
 
@@ -631,6 +648,7 @@ impl<'a> MovePickerForQSearch<'a> {
             recapture_square,
             stage,
             tt_move: ttm,
+            depth,
             move_list: MoveList::new(),
         }
     }
@@ -654,20 +672,26 @@ impl<'a> MovePickerForQSearch<'a> {
                 }
                 StagesForQSearch::QCapture => {
                     let m = select_best_qcapture(self.move_list.slice_mut(self.cur), &mut self.cur, self.tt_move);
-                    return m;
-                    //if m != Move::NONE {
-                    //    return m;
-                    //}
-                    //if self.depth != Depth::QS_CHECKS {
-                    //    return Move::NONE;
-                    //}
-                    //self.stage = self.stage.next_variant().unwrap();
+                    if m.is_some() {
+                        return m;
+                    }
+                    // Quiet checks only at the first ply of qsearch.
+                    if self.depth != Depth::QS_CHECKS {
+                        return None;
+                    }
+                    self.stage = self.stage.next_variant().unwrap();
                 }
                 StagesForQSearch::QRecapture => {
                     return select_best_qrecapture(self.move_list.slice_mut(self.cur), &mut self.cur, self.tt_move);
                 }
-                //StagesForQSearch::QCheckInit => {}
-                //StagesForQSearch::QCheck => {}
+                StagesForQSearch::QCheckInit => {
+                    self.cur = 0;
+                    self.move_list.generate::<QuietChecksType>(pos, 0);
+                    self.stage = self.stage.next_variant().unwrap();
+                }
+                StagesForQSearch::QCheck => {
+                    return select_next_quiet_check(self.move_list.slice(self.cur), &mut self.cur, pos, self.tt_move);
+                }
                 StagesForQSearch::EvasionInit => {
                     self.cur = 0;
                     self.move_list.generate::<EvasionsType>(pos, 0);
@@ -973,6 +997,19 @@ mod tests {
         let m = mp.next_move(&pos);
         assert_eq!(m.unwrap(), Move::new_unpromote(Square::SQ88, Square::SQ97, Piece::B_BISHOP)); // Capture
         let m = mp.next_move(&pos);
+        assert_eq!(m.unwrap(), Move::new_unpromote(Square::SQ88, Square::SQ55, Piece::B_BISHOP)); // QCheck
+        let m = mp.next_move(&pos);
+        assert!(m.is_none());
+
+        // No quiet checks after the first ply of qsearch.
+        let mut mp = MovePickerForQSearch::new(&mh, &cph, &ch, &pos, recapture_square, tt_move, Depth::QS_NO_CHECKS);
+        let m = mp.next_move(&pos);
+        assert_eq!(m, tt_move); // QSearchTT
+        let m = mp.next_move(&pos);
+        assert_eq!(m.unwrap(), Move::new_unpromote(Square::SQ88, Square::SQ44, Piece::B_BISHOP)); // Capture
+        let m = mp.next_move(&pos);
+        assert_eq!(m.unwrap(), Move::new_unpromote(Square::SQ88, Square::SQ97, Piece::B_BISHOP)); // Capture
+        let m = mp.next_move(&pos);
         assert!(m.is_none());
     }
 
@@ -105,6 +105,27 @@ impl Move {
     pub fn new_from_usi(usi_move: &UsiMove, pos: &Position) -> Option<Move> {
         Self::new_from_usi_str(usi_move.as_str(), pos)
     }
+    // The lower 16 bits of the move. The moved piece is restored from pos.
+    pub fn new_from_u16(mv16: u16, pos: &Position) -> Option<Move> {
+        let m = Move(std::num::NonZeroU32::new(u32::from(mv16))?);
+        if !m.to().is_ok() || (!m.is_drop() && !m.from().is_ok()) {
+            return None;
+        }
+        let m = if m.is_drop() {
+            m
+        } else {
+            Move(unsafe {
+                std::num::NonZeroU32::new_unchecked(m.0.get() | ((pos.piece_on(m.from()).0 as u32) << Move::MOVED_PIECE_SHIFT))
+            })
+        };
+        if !pos.pseudo_legal::<NotSearchingType>(m) || !pos.legal(m) {
+            return None;
+        }
+        Some(m)
+    }
+    pub fn to_u16(self) -> u16 {
+        u32::from(self.0) as u16
+    }
     pub fn new_from_csa_str(s: &str, pos: &Position) -> Option<Move> {
         let m;
         let mut v: Vec<char> = s.chars().collect();
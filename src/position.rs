@@ -1,8 +1,11 @@
 use crate::bitboard::*;
+use crate::bod::*;
+use crate::csa::*;
 #[cfg(feature = "kppt")]
 use crate::evaluate::kppt::*;
 use crate::hand::*;
 use crate::huffman_code::*;
+use crate::movegen::*;
 use crate::movetypes::*;
 use crate::piecevalue::*;
 use crate::sfen::*;
@@ -38,6 +41,26 @@ pub enum Repetition {
     Inferior,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnteringKingRule {
+    None,
+    CsaRule24, // The declaration with 31 points.
+    CsaRule27, // The declaration with 28 points (black) or 27 points (white).
+    TryRule,   // The king moving to the square where the opponent king starts wins.
+}
+
+impl EnteringKingRule {
+    pub fn new_from_str(s: &str) -> Option<EnteringKingRule> {
+        match s {
+            "None" => Some(EnteringKingRule::None),
+            "CSARule24" => Some(EnteringKingRule::CsaRule24),
+            "CSARule27" => Some(EnteringKingRule::CsaRule27),
+            "TryRule" => Some(EnteringKingRule::TryRule),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy)] // Copy is needed for MaybeUninit
 pub struct CheckInfo {
     blockers_and_pinners_for_king: [(Bitboard, Bitboard); Color::NUM], // color is color_of_king
@@ -403,6 +426,8 @@ pub struct PositionBase {
     game_ply: i32,
     king_squares: [Square; Color::NUM],
     side_to_move: Color,
+    // The points of the pieces white dropped in a handicap game. 0 for an even game.
+    handicap_points: u32,
 }
 
 impl PositionBase {
@@ -425,6 +450,7 @@ impl PositionBase {
             game_ply: 0,
             king_squares: [Square(0), Square(0)],
             side_to_move: Color::BLACK,
+            handicap_points: 0,
         };
         let rank_str_vec: Vec<&str> = board_str.split('/').collect();
         if rank_str_vec.len() != Rank::NUM {
@@ -469,6 +495,10 @@ impl PositionBase {
                 Some(sq) => pos.king_squares[c.0 as usize] = sq,
                 None => return Err(SfenError::KingIsNothing { c: *c }),
             }
+            // king_squares holds only one king for each color.
+            if bb.to_bool() {
+                return Err(SfenError::TooManyKings { c: *c });
+            }
         }
         match side_to_move_str {
             "b" => pos.side_to_move = Color::BLACK,
@@ -569,8 +599,97 @@ impl PositionBase {
         check_pieces(&pos, &[PieceType::GOLD], 4)?;
         check_pieces(&pos, &[PieceType::BISHOP, PieceType::HORSE], 2)?;
         check_pieces(&pos, &[PieceType::ROOK, PieceType::DRAGON], 2)?;
+        // The engine can't handle these positions even if the rules are ignored, so "Illegal_Position" can't allow them.
+        // The evasions assume at most two checkers, and capturing the king breaks king_squares and is_ok().
+        pos.validate_checks()?;
+        // Only the start positions of handicap games are known as handicap games.
+        if handicap_name(&sfen_slice.join(" ")).is_some() {
+            pos.handicap_points = pos.missing_piece_points();
+        }
         Ok(pos)
     }
+    // The points of the pieces which are neither on the board nor in the hands. e.g. 5 for "角落ち".
+    // Big pieces are 5 points and small pieces are 1 point. The total points of all the pieces are 54.
+    fn missing_piece_points(&self) -> u32 {
+        let board_points = self
+            .board
+            .iter()
+            .filter(|pc| **pc != Piece::EMPTY && !pc.is_king())
+            .map(|pc| match PieceType::new(*pc) {
+                PieceType::BISHOP | PieceType::ROOK | PieceType::HORSE | PieceType::DRAGON => 5,
+                _ => 1,
+            })
+            .sum::<u32>();
+        let hand_points = self
+            .hands
+            .iter()
+            .map(|hand| {
+                hand.num(PieceType::PAWN)
+                    + hand.num(PieceType::LANCE)
+                    + hand.num(PieceType::KNIGHT)
+                    + hand.num(PieceType::SILVER)
+                    + hand.num(PieceType::GOLD)
+                    + (hand.num(PieceType::BISHOP) + hand.num(PieceType::ROOK)) * 5
+            })
+            .sum::<u32>();
+        54u32.saturating_sub(board_points + hand_points)
+    }
+    fn validate_checks(&self) -> Result<(), SfenError> {
+        let us = self.side_to_move();
+        let them = us.inverse();
+        if self.attackers_to(us, self.king_square(them), &self.occupied_bb()).to_bool() {
+            return Err(SfenError::SideNotToMoveIsInCheck);
+        }
+        let number = self
+            .attackers_to(them, self.king_square(us), &self.occupied_bb())
+            .count_ones();
+        if 2 < number {
+            return Err(SfenError::TooManyCheckers { number });
+        }
+        Ok(())
+    }
+    // Check the rules which new_from_sfen_args() doesn't check. e.g. two pawns on a file.
+    pub fn validate(&self) -> Result<(), SfenError> {
+        self.validate_checks()?;
+        for &c in Color::ALL.iter() {
+            for &file in File::ALL.iter() {
+                let pawns = Rank::ALL
+                    .iter()
+                    .filter(|&&rank| self.piece_on(Square::new(file, rank)) == Piece::new(c, PieceType::PAWN))
+                    .count();
+                if 1 < pawns {
+                    return Err(SfenError::TwoPawnsOnFile {
+                        c,
+                        file: file.to_usi_char(),
+                    });
+                }
+            }
+        }
+        for &sq in Square::ALL.iter() {
+            let pc = self.piece_on(sq);
+            if pc == Piece::EMPTY {
+                continue;
+            }
+            // The rank from the point of view of the owner. 0 is the farthest rank.
+            let rank = if Color::new(pc) == Color::BLACK {
+                Rank::new(sq).0
+            } else {
+                Rank::NUM as i32 - 1 - Rank::new(sq).0
+            };
+            let cannot_move = match PieceType::new(pc) {
+                PieceType::PAWN | PieceType::LANCE => rank == 0,
+                PieceType::KNIGHT => rank <= 1,
+                _ => false,
+            };
+            if cannot_move {
+                return Err(SfenError::PieceCannotMove {
+                    token: pc.to_usi_str().to_string(),
+                    square: sq.to_usi_string(),
+                });
+            }
+        }
+        Ok(())
+    }
     pub fn new_from_huffman_coded_position(hcp: &HuffmanCodedPosition) -> Result<PositionBase> {
         let mut bs = BitStreamReader::new(&hcp.buf);
         let mut pos = PositionBase {
@@ -582,6 +701,7 @@ impl PositionBase {
             game_ply: 0,
             king_squares: [Square(0), Square(0)],
             side_to_move: Color::BLACK,
+            handicap_points: 0,
         };
         pos.side_to_move = Color(i32::from(bs.get_bit_from_lsb()));
         pos.king_squares[Color::BLACK.0 as usize] = {
@@ -971,6 +1091,14 @@ impl Position {
         debug_assert!(pos.is_ok());
         Ok(pos)
     }
+    // A CSA board diagram. e.g. "P1-KY-KE-GI-KI-OU-KI-GI-KE-KY\n...\nP+00FU\n+"
+    pub fn new_from_csa_board(csa: &str) -> Result<Position, CsaBoardError> {
+        Ok(Self::new_from_sfen(&csa_board_to_sfen(csa)?)?)
+    }
+    // A BOD board diagram of KIF.
+    pub fn new_from_bod(bod: &str) -> Result<Position> {
+        Self::new_from_sfen(&bod_to_sfen(bod)?).map_err(|e| anyhow!("sfen error: {}", e))
+    }
     pub fn new_from_huffman_coded_position(hcp: &HuffmanCodedPosition) -> Result<Position> {
         let base = PositionBase::new_from_huffman_coded_position(hcp)?;
         let state = StateInfo::new_from_position(&base);
@@ -1132,6 +1260,10 @@ impl Position {
     pub fn pinners_for_king(&self, color_of_king: Color) -> Bitboard {
         unsafe { (*self.st().check_info.as_ptr()).pinners_for_king(color_of_king) }
     }
+    // The squares where a piece of pt gives a direct check to the king of the opponent of the side to move.
+    pub fn check_squares(&self, pt: PieceType) -> Bitboard {
+        unsafe { (*self.st().check_info.as_ptr()).check_squares[pt.0 as usize] }
+    }
     pub fn pseudo_legal<T: IsSearchingTrait>(&self, m: Move) -> bool {
         let us = self.side_to_move();
         let to;
@@ -1519,7 +1651,63 @@ impl Position {
         }
         Repetition::Not
     }
+    // The plies back to the previous occurrences of the current position from the nearest.
+    // Unlike is_repetition(), this looks back to the start of the game.
+    fn repetition_plies(&self) -> impl Iterator<Item = i32> + '_ {
+        let current = self.states.len() - 1;
+        (4..=self.st().plies_from_null)
+            .step_by(2)
+            .filter(move |i| self.states[current - *i as usize].key() == self.key())
+    }
+    // The number of occurrences of the current position in the game including the current one.
+    pub fn repetition_count(&self) -> usize {
+        self.repetition_plies().count() + 1
+    }
+    // 千日手 of the whole game.
+    // The same position appearing four times is a draw, but the side which checks continuously from the first occurrence loses.
+    // This is slower than is_repetition(). It is used only near the root.
+    pub fn game_repetition(&self) -> Repetition {
+        // The first occurrence of the four.
+        match self.repetition_plies().nth(2) {
+            Some(i) => {
+                let us = self.side_to_move();
+                if i <= self.st().continuous_check(us) {
+                    Repetition::Lose
+                } else if i <= self.st().continuous_check(us.inverse()) {
+                    Repetition::Win
+                } else {
+                    Repetition::Draw
+                }
+            }
+            None => Repetition::Not,
+        }
+    }
+    // The points needed for the entering king declaration of the CSA 27 point rule.
+    pub fn entering_king_points_threshold(&self, c: Color) -> u32 {
+        self.declaration_points_threshold(c, EnteringKingRule::CsaRule27).unwrap()
+    }
+    // None if the rule doesn't have the declaration.
+    fn declaration_points_threshold(&self, c: Color, rule: EnteringKingRule) -> Option<u32> {
+        let (black, white) = match rule {
+            EnteringKingRule::CsaRule24 => (31u32, 31u32),
+            EnteringKingRule::CsaRule27 => (28u32, 27u32),
+            EnteringKingRule::None | EnteringKingRule::TryRule => return None,
+        };
+        Some(if c == Color::BLACK {
+            black
+        } else {
+            white.saturating_sub(self.base.handicap_points)
+        })
+    }
     pub fn is_entering_king_win(&self) -> bool {
+        self.is_declaration_win(EnteringKingRule::CsaRule27)
+    }
+    pub fn is_declaration_win(&self, rule: EnteringKingRule) -> bool {
+        let threshold = match self.declaration_points_threshold(self.side_to_move(), rule) {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+
         // CSA rule.
 
         // 一 宣言側の手番である。
@@ -1545,7 +1733,9 @@ impl Position {
         // 三 宣言側が、大駒5点小駒1点で計算して
         //     先手の場合28点以上の持点がある。
         //     後手の場合27点以上の持点がある。
+        //     (24点法では先後とも31点以上。)
         //     点数の対象となるのは、宣言側の持駒と敵陣三段目以内に存在する玉を除く宣言側の駒のみである。
+        // 駒落ちの場合、上手(後手)の基準点は落とした駒の点数だけ少なくなる。
         let own_big_pieces_count =
             (self.pieces_cpppp(us, PieceType::BISHOP, PieceType::ROOK, PieceType::HORSE, PieceType::DRAGON)
                 & Bitboard::opponent_field_mask(us))
@@ -1559,12 +1749,26 @@ impl Position {
             + hand.num(PieceType::SILVER)
             + hand.num(PieceType::GOLD)
             + (own_big_pieces_count + hand.num(PieceType::BISHOP) + hand.num(PieceType::ROOK)) * 5;
-        let thresh = if us == Color::BLACK { 28 } else { 27 };
-        if val < thresh {
+        if val < threshold {
             return false;
         }
         true
     }
+    // The king move to the square where the opponent king starts (5a or 5i) for the try rule.
+    pub fn try_rule_move(&self) -> Option<Move> {
+        let us = self.side_to_move();
+        let to = if us == Color::BLACK { Square::SQ51 } else { Square::SQ59 };
+        let from = self.king_square(us);
+        if !ATTACK_TABLE.king.attack(from).is_set(to) || self.pieces_c(us).is_set(to) {
+            return None;
+        }
+        let m = Move::new_unpromote(from, to, self.piece_on(from));
+        if self.legal(m) {
+            Some(m)
+        } else {
+            None
+        }
+    }
     #[inline]
     pub fn key(&self) -> Key {
         self.st().key()
@@ -1604,6 +1808,13 @@ impl Position {
         self.base.to_csa_string()
     }
     #[inline]
+    pub fn validate(&self) -> Result<(), SfenError> {
+        self.base.validate()
+    }
+    pub fn to_bod_string(&self) -> String {
+        position_to_bod_string(self)
+    }
+    #[inline]
     pub fn checkers(&self) -> Bitboard {
         unsafe { self.st().checkers_bb.assume_init() }
     }
@@ -2701,6 +2912,38 @@ impl Position {
 
         None
     }
+    // Mate in 3 plies. All the checks of the side to move, all the evasions and mate_move_in_1ply().
+    // Some mates are missed because mate_move_in_1ply() is used for the last move.
+    pub fn mate_move_in_3ply(&mut self) -> Option<Move> {
+        debug_assert!(!self.in_check());
+        let mut checks = MoveList::new();
+        checks.generate::<ChecksType>(self, 0);
+        for i in 0..checks.size {
+            let m = checks.slice(0)[i].mv;
+            if !self.legal(m) {
+                continue;
+            }
+            self.do_move(m, true);
+            let mut evasions = MoveList::new();
+            evasions.generate::<LegalAllType>(self, 0);
+            let mut mated = true;
+            for j in 0..evasions.size {
+                let evasion = evasions.slice(0)[j].mv;
+                let gives_check = self.gives_check(evasion);
+                self.do_move(evasion, gives_check);
+                mated = !gives_check && self.mate_move_in_1ply().is_some();
+                self.undo_move(evasion);
+                if !mated {
+                    break;
+                }
+            }
+            self.undo_move(m);
+            if mated {
+                return Some(m);
+            }
+        }
+        None
+    }
     #[allow(dead_code)]
     fn is_ok(&self) -> bool {
         if self.pieces_c(Color::BLACK).and_to_bool(self.pieces_c(Color::WHITE)) {
@@ -2930,6 +3173,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_position_validate() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let sfen = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+                assert!(Position::new_from_sfen(sfen).unwrap().validate().is_ok());
+
+                // The engine can't handle them.
+                assert!(matches!(
+                    Position::new_from_sfen("4k4/9/9/9/9/9/9/9/3KK4 b - 1"),
+                    Err(SfenError::TooManyKings { c: Color::BLACK })
+                ));
+                assert!(matches!(
+                    Position::new_from_sfen("4k4/4G4/9/9/9/9/9/9/4K4 b - 1"),
+                    Err(SfenError::SideNotToMoveIsInCheck)
+                ));
+                assert!(matches!(
+                    Position::new_from_sfen("4k4/9/9/9/b8/9/3n1n3/4r4/4K4 b - 1"),
+                    Err(SfenError::TooManyCheckers { number: 4 })
+                ));
+
+                // They are against the rules, but new_from_sfen() accepts them.
+                assert!(matches!(
+                    Position::new_from_sfen("4k4/9/9/9/9/4P4/4P4/9/4K4 b - 1").unwrap().validate(),
+                    Err(SfenError::TwoPawnsOnFile {
+                        c: Color::BLACK,
+                        file: '5'
+                    })
+                ));
+                let sfens = [
+                    "P3k4/9/9/9/9/9/9/9/4K4 b - 1",
+                    "L3k4/9/9/9/9/9/9/9/4K4 b - 1",
+                    "4k4/N8/9/9/9/9/9/9/4K4 b - 1",
+                    "4k4/9/9/9/9/9/9/9/3pK4 b - 1",
+                ];
+                for sfen in sfens.iter() {
+                    assert!(matches!(
+                        Position::new_from_sfen(sfen).unwrap().validate(),
+                        Err(SfenError::PieceCannotMove { .. })
+                    ));
+                }
+                match Position::new_from_sfen("4k4/9/9/9/9/9/9/8n/4K4 b - 1").unwrap().validate() {
+                    Err(SfenError::PieceCannotMove { token, square }) => {
+                        assert_eq!(token, "n");
+                        assert_eq!(square, "1h");
+                    }
+                    _ => unreachable!(),
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
     #[test]
     fn test_position_attackers_to() {
         let sfens = ["lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1"];
@@ -3198,6 +3496,112 @@ mod tests {
         assert_eq!(sfen.unwrap(), START_SFEN);
     }
 
+    #[test]
+    fn test_new_from_csa_board() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let sfens = [
+                    START_SFEN,
+                    "l4S2l/4g1gs1/5p1p1/pr2N1pkp/4Gn3/PP3PPPP/2GPP4/1K7/L3r+s2L w BS2N5Pb 20",
+                    "4k4/9/9/9/9/9/9/9/4K4 b RB2G2S2N2L9Prb2g2s2n2l9p 1",
+                ];
+                for sfen in sfens.iter() {
+                    let pos = Position::new_from_sfen(sfen).unwrap();
+                    let from_csa = Position::new_from_csa_board(&pos.to_csa_string()).unwrap();
+                    // The game ply isn't in CSA.
+                    assert_eq!(
+                        from_csa.to_sfen().rsplit_once(' ').unwrap().0,
+                        sfen.rsplit_once(' ').unwrap().0
+                    );
+                }
+
+                let csa = "PI\n-\n";
+                assert_eq!(
+                    Position::new_from_csa_board(csa).unwrap().to_sfen(),
+                    "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"
+                );
+                let csa = "P-11OU\nP+99OU\nP+33KI00KI\nP-00AL\n+\n";
+                assert_eq!(
+                    Position::new_from_csa_board(csa).unwrap().to_sfen(),
+                    "8k/9/6G2/9/9/9/9/9/K8 b G2r2b2g4s4n4l18p 1"
+                );
+
+                assert!(matches!(
+                    Position::new_from_csa_board("PI\n"),
+                    Err(CsaBoardError::SideToMoveIsNothing)
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("P1-KY-KE\nXX\n+\n"),
+                    Err(CsaBoardError::InvalidLine { .. })
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("P1-KY-KE-GI-KI-OU-KI-GI-KE-KY-KY\n+\n"),
+                    Err(CsaBoardError::InvalidNumberOfSquares { .. })
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("P1-KY-KE-GI-KI-OU-KI-GI-KE-XX\n+\n"),
+                    Err(CsaBoardError::InvalidPiece { .. })
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("PI55FU\n+\n"),
+                    Err(CsaBoardError::NoPieceToRemove { .. })
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("P-11OU\nP+11OU\n+\n"),
+                    Err(CsaBoardError::SquareAlreadyOccupied { .. })
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("P-11OU\nP+99OU\nP+00AL\nP-00AL\n+\n"),
+                    Err(CsaBoardError::AllRemainingTwice { .. })
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("P-11OU\n+\n"),
+                    Err(CsaBoardError::Sfen(SfenError::KingIsNothing { .. }))
+                ));
+                assert!(matches!(
+                    Position::new_from_csa_board("PI\nP+00KA\n+\n"),
+                    Err(CsaBoardError::Sfen(SfenError::InvalidNumberOfBishops { .. }))
+                ));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_entering_king_rules() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                // 28 points.
+                let pos = Position::new_from_sfen("1p7/KRRBBPPPP/NN7/9/9/9/9/9/8k b 2P 1").unwrap();
+                assert!(pos.is_declaration_win(EnteringKingRule::CsaRule27));
+                assert!(!pos.is_declaration_win(EnteringKingRule::CsaRule24));
+                assert!(!pos.is_declaration_win(EnteringKingRule::None));
+                assert!(!pos.is_declaration_win(EnteringKingRule::TryRule));
+                // 31 points.
+                let pos = Position::new_from_sfen("1p7/KRRBBPPPP/NN7/9/9/9/9/9/8k b 3S2P 1").unwrap();
+                assert!(pos.is_declaration_win(EnteringKingRule::CsaRule24));
+
+                let pos = Position::new_from_sfen("9/4K4/9/9/9/9/9/9/k8 b - 1").unwrap();
+                assert_eq!(pos.try_rule_move().unwrap().to_usi_string(), "5b5a");
+                let pos = Position::new_from_sfen("9/9/9/9/9/9/9/4k4/K8 w - 1").unwrap();
+                assert_eq!(pos.try_rule_move().unwrap().to_usi_string(), "5h5i");
+                let pos = Position::new_from_sfen("4p4/4K4/9/9/9/9/9/9/k8 b - 1").unwrap();
+                assert_eq!(pos.try_rule_move().unwrap().to_usi_string(), "5b5a"); // capture
+                let pos = Position::new_from_sfen("r8/4K4/9/9/9/9/9/9/k8 b - 1").unwrap();
+                assert!(pos.try_rule_move().is_none()); // 5a is attacked.
+                let pos = Position::new_from_sfen("4G4/4K4/9/9/9/9/9/9/k8 b - 1").unwrap();
+                assert!(pos.try_rule_move().is_none()); // 5a is occupied by own piece.
+                let pos = Position::new_from_sfen("9/9/4K4/9/9/9/9/9/k8 b - 1").unwrap();
+                assert!(pos.try_rule_move().is_none()); // too far.
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
     #[test]
     fn test_is_entering_king_win() {
         std::thread::Builder::new()
@@ -3263,6 +3667,17 @@ mod tests {
                 assert!(!pos.is_entering_king_win()); // less than 10 own pieces on the opponent field.
                 let pos = Position::new_from_sfen("K8/9/9/9/9/n8/n8/kr1bppppp/1Pggggs2 w b 2").unwrap();
                 assert!(!pos.is_entering_king_win()); // less than 27 point.
+
+                // Handicap games. The thresholds of white are less than 27 points.
+                assert_eq!(Position::new().entering_king_points_threshold(Color::WHITE), 27);
+                let mut pos = Position::new_from_sfen(handicap_sfen("角落ち").unwrap()).unwrap();
+                assert_eq!(pos.entering_king_points_threshold(Color::BLACK), 28);
+                assert_eq!(pos.entering_king_points_threshold(Color::WHITE), 22);
+                let m = Move::new_from_usi_str("3c3d", &pos).unwrap();
+                pos.do_move(m, pos.gives_check(m));
+                assert_eq!(pos.entering_king_points_threshold(Color::WHITE), 22);
+                let pos = Position::new_from_sfen(handicap_sfen("十枚落ち").unwrap()).unwrap();
+                assert_eq!(pos.entering_king_points_threshold(Color::WHITE), 9);
             })
             .unwrap()
             .join()
@@ -3308,6 +3723,46 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_game_repetition() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let mut pos = Position::new_from_sfen("4k4/9/9/9/9/9/9/9/4K4 b - 1").unwrap();
+                for (i, m) in ["5i5h", "5a5b", "5h5i", "5b5a"].iter().cycle().take(12).enumerate() {
+                    let m = Move::new_from_usi_str(m, &pos).unwrap();
+                    pos.do_move(m, pos.gives_check(m));
+                    let ply = i + 1;
+                    assert_eq!(pos.repetition_count(), ply / 4 + 1);
+                    let expected = if ply == 12 { Repetition::Draw } else { Repetition::Not };
+                    assert_eq!(pos.game_repetition(), expected);
+                }
+
+                // Black checks continuously.
+                let mut pos = Position::new_from_sfen("7k1/9/9/9/9/9/9/9/K7R b - 1").unwrap();
+                let moves = ["1i2i", "2a3a"]
+                    .iter()
+                    .chain(["2i3i", "3a2a", "3i2i", "2a3a"].iter().cycle().take(12));
+                for (i, m) in moves.enumerate() {
+                    let m = Move::new_from_usi_str(m, &pos).unwrap();
+                    pos.do_move(m, pos.gives_check(m));
+                    // The positions after 1i2i and 2a3a appear four times at ply 13 and 14.
+                    let expected = match i + 1 {
+                        13 => Repetition::Win,
+                        14 => Repetition::Lose,
+                        _ => Repetition::Not,
+                    };
+                    assert_eq!(pos.game_repetition(), expected);
+                }
+                let m = Move::new_from_usi_str("2i3i", &pos).unwrap();
+                pos.do_move(m, pos.gives_check(m));
+                assert_eq!(pos.game_repetition(), Repetition::Win);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
     #[test]
     fn test_mate_move_in_1ply() {
         std::thread::Builder::new()
@@ -3372,6 +3827,32 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_mate_move_in_3ply() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                fn f(sfen: &str, expected: Option<&str>) {
+                    let mut pos = Position::new_from_sfen(sfen).unwrap();
+                    let m = pos.mate_move_in_3ply();
+                    let m = m.map(|m| m.to_usi_string());
+                    let m = m.as_deref();
+                    assert_eq!((sfen, m), (sfen, expected));
+                    assert_eq!(pos.to_sfen(), sfen);
+                }
+                f("8l/7k1/9/7P1/9/9/9/9/4K4 b 2G 1", Some("G*2c"));
+                f("8l/7k1/9/7P1/9/9/9/9/4K4 b G 1", None);
+                f("7k1/9/6P1P/9/9/9/9/9/4K4 b 2G 1", Some("G*1b"));
+                f("7kl/9/6P2/9/9/9/9/9/4K4 b 2G 1", None);
+                f("8k/9/8P/9/9/9/9/9/8K b G 1", Some("G*1b")); // Mate in 1 ply is also found.
+                f("4k4/9/9/9/9/9/9/9/4K4 b - 1", None);
+                f("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1", None);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
     #[test]
     fn test_effect_bb_of_checker_where_king_cannot_escape() {
         std::thread::Builder::new()
@@ -1,3 +1,4 @@
+use crate::dfpn::*;
 #[cfg(feature = "kppt")]
 use crate::evaluate::kppt::*;
 use crate::movegen::*;
@@ -38,11 +39,14 @@ pub struct LimitsType {
     pub inc: [std::time::Duration; 2],
     pub depth: Option<u32>,
     pub movetime: Option<std::time::Duration>,
+    pub byoyomi: Option<std::time::Duration>, // Time per move after the main time runs out. Unlike movetime, this is combined with time.
     pub mate: Option<u64>,
     pub perft: Option<u32>,
     pub infinite: Option<()>, // Is bool more appropriate?
     pub nodes: Option<u64>,
     pub start_time: Option<std::time::Instant>,
+    pub search_moves: Vec<Move>, // If this isn't empty, only these root moves are searched.
+    pub ignore_moves: Vec<Move>, // These root moves aren't searched.
 }
 
 impl LimitsType {
@@ -53,11 +57,14 @@ impl LimitsType {
             inc: [duration; 2],
             depth: None,
             movetime: None,
+            byoyomi: None,
             mate: None,
             perft: None,
             infinite: None,
             nodes: None,
             start_time: None,
+            search_moves: vec![],
+            ignore_moves: vec![],
         }
     }
     pub fn use_time_management(&self) -> bool {
@@ -68,6 +75,10 @@ impl LimitsType {
             && self.perft.is_none()
             && self.infinite.is_none()
     }
+    // Whether the root move is searched under searchmoves and ignoremoves.
+    pub fn is_searched_root_move(&self, m: Move) -> bool {
+        (self.search_moves.is_empty() || self.search_moves.contains(&m)) && !self.ignore_moves.contains(&m)
+    }
 }
 
 #[derive(Clone, Eq)]
@@ -106,7 +117,8 @@ impl RootMove {
             pv: vec![m],
         }
     }
-    pub fn extract_pv_from_tt(&mut self, pos: &mut Position, tt: *mut TranspositionTable) {
+    // max_moves_to_draw: The PV doesn't go beyond this number of moves of the game. 0 means no limit.
+    pub fn extract_pv_from_tt(&mut self, pos: &mut Position, tt: *mut TranspositionTable, max_moves_to_draw: i32) {
         let mut m = self.pv[0];
         debug_assert!(pos.pseudo_legal::<SearchingType>(m));
         let mut ply = 0;
@@ -132,6 +144,7 @@ impl RootMove {
                 && pos.legal(m)
                 && ply < MAX_PLY
                 && pos.is_repetition() == Repetition::Not
+                && (max_moves_to_draw == 0 || pos.ply() <= max_moves_to_draw)
         } {}
         for m in self.pv[..ply as usize].iter().rev() {
             pos.undo_move(*m);
@@ -217,8 +230,8 @@ pub fn stat_bonus(depth: Depth) -> i32 {
     }
 }
 
-pub fn value_draw(nodes: i64) -> Value {
-    Value::DRAW + Value(2 * (nodes as i32 & 1) - 1)
+pub fn value_draw(draw_value: Value, nodes: i64) -> Value {
+    draw_value + Value(2 * (nodes as i32 & 1) - 1)
 }
 
 pub fn update_continuation_histories(stack: &mut [Stack], pc: Piece, to: Square, bonus: i32) {
@@ -263,6 +276,36 @@ impl Reductions {
     }
 }
 
+// The results of Position::mate_move_in_3ply(). Each thread has its own small cache.
+pub struct MateCache {
+    entries: Vec<(Key, Option<Move>)>,
+}
+
+impl MateCache {
+    const SIZE: usize = 1 << 16;
+
+    pub fn new() -> MateCache {
+        MateCache {
+            entries: vec![(Key(0), None); MateCache::SIZE],
+        }
+    }
+    pub fn clear(&mut self) {
+        self.entries.fill((Key(0), None));
+    }
+    // Some(None) means that it is already known that there is no mate in 3 plies.
+    pub fn probe(&self, key: Key) -> Option<Option<Move>> {
+        let (entry_key, mv) = self.entries[key.0 as usize & (MateCache::SIZE - 1)];
+        if entry_key == key {
+            Some(mv)
+        } else {
+            None
+        }
+    }
+    pub fn save(&mut self, key: Key, mv: Option<Move>) {
+        self.entries[key.0 as usize & (MateCache::SIZE - 1)] = (key, mv);
+    }
+}
+
 pub const SKIP_SIZE: [i32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
 pub const SKIP_PHASE: [i32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
 
@@ -339,29 +382,28 @@ impl Mate {
             position: Position::new_from_position(pos, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0))),
         }
     }
-    pub fn go(&self, timeout_milli: u64) {
+    // The output is "checkmate <pv>", "checkmate nomate" or "checkmate timeout".
+    // "stop" is treated as timeout.
+    pub fn go(
+        &mut self,
+        table: &mut MateTable,
+        timeout_milli: u64,
+        stop: &std::sync::Arc<std::sync::atomic::AtomicBool>,
+        hide_all_output: bool,
+    ) -> String {
         let timeout = if timeout_milli == 0 {
             None
         } else {
             Some(std::time::Duration::from_millis(timeout_milli))
         };
-        let r = tsumeshogi_solver::solve(&self.position.to_sfen(), tsumeshogi_solver::Backend::Yasai, timeout);
-        match r {
-            Ok(v) => {
-                let mut pos =
-                    Position::new_from_position(&self.position, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
-                let mut pv = vec![];
-                for m in v {
-                    let m = m.chars().skip(1).collect::<String>();
-                    let m = Move::new_from_csa_str(&m, &pos).unwrap();
-                    pv.push(m.to_usi_string());
-                    pos.do_move(m, pos.gives_check(m));
-                }
-                println!("checkmate {}", pv.join(" "));
-            }
-            Err(_) => {
-                println!("checkmate none");
-            }
+        let mut dfpn = DfPn::new(table, stop.clone(), hide_all_output);
+        match dfpn.solve(&mut self.position, timeout) {
+            MateResult::Mate(pv) => format!(
+                "checkmate {}",
+                pv.iter().map(|m| m.to_usi_string()).collect::<Vec<_>>().join(" ")
+            ),
+            MateResult::NoMate => "checkmate nomate".to_string(),
+            MateResult::Timeout => "checkmate timeout".to_string(),
         }
     }
 }
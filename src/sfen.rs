@@ -3,6 +3,72 @@ use thiserror::Error;
 
 pub const START_SFEN: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
 
+// The start positions of handicap games by the names of KIF "手合割".
+// The handicap giver is white and moves first.
+#[rustfmt::skip]
+pub const HANDICAP_SFENS: [(&str, &str); 16] = [
+    ("平手",       "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1"),
+    ("香落ち",     "lnsgkgsn1/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("右香落ち",   "1nsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("角落ち",     "lnsgkgsnl/1r7/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("飛車落ち",   "lnsgkgsnl/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("飛香落ち",   "lnsgkgsn1/7b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("二枚落ち",   "lnsgkgsnl/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("三枚落ち",   "lnsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("四枚落ち",   "1nsgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("五枚落ち",   "2sgkgsn1/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("左五枚落ち", "1nsgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("六枚落ち",   "2sgkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("左七枚落ち", "2sgkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("右七枚落ち", "3gkgs2/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("八枚落ち",   "3gkg3/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+    ("十枚落ち",   "4k4/9/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL w - 1"),
+];
+
+pub fn handicap_sfen(name: &str) -> Option<&'static str> {
+    HANDICAP_SFENS.iter().find(|(n, _)| *n == name).map(|(_, sfen)| *sfen)
+}
+
+// The handicap names in ASCII and the names of HANDICAP_SFENS.
+// They are the same as "initial.preset" of JKF (JSON Kifu Format).
+pub const HANDICAP_PRESETS: [(&str, &str); 16] = [
+    ("HIRATE", "平手"),
+    ("KY", "香落ち"),
+    ("KY_R", "右香落ち"),
+    ("KA", "角落ち"),
+    ("HI", "飛車落ち"),
+    ("HIKY", "飛香落ち"),
+    ("2", "二枚落ち"),
+    ("3", "三枚落ち"),
+    ("4", "四枚落ち"),
+    ("5", "五枚落ち"),
+    ("5_L", "左五枚落ち"),
+    ("6", "六枚落ち"),
+    ("7_L", "左七枚落ち"),
+    ("7_R", "右七枚落ち"),
+    ("8", "八枚落ち"),
+    ("10", "十枚落ち"),
+];
+
+pub fn handicap_preset_to_name(preset: &str) -> Option<&'static str> {
+    HANDICAP_PRESETS.iter().find(|(p, _)| *p == preset).map(|(_, name)| *name)
+}
+
+pub fn handicap_name_to_preset(name: &str) -> Option<&'static str> {
+    HANDICAP_PRESETS.iter().find(|(_, n)| *n == name).map(|(preset, _)| *preset)
+}
+
+// The name of the handicap whose start position is sfen. The game ply is ignored.
+pub fn handicap_name(sfen: &str) -> Option<&'static str> {
+    fn without_ply(s: &str) -> Vec<&str> {
+        s.split_whitespace().take(3).collect()
+    }
+    HANDICAP_SFENS
+        .iter()
+        .find(|(_, handicap_sfen)| without_ply(handicap_sfen) == without_ply(sfen))
+        .map(|(name, _)| *name)
+}
+
 #[rustfmt::skip]
 #[derive(Debug, Error)]
 pub enum SfenError {
@@ -44,4 +110,14 @@ pub enum SfenError {
     SameHandPieceTwice { token: String },
     #[error("{c:?} king is nothing.")]
     KingIsNothing { c: Color },
+    #[error("{c:?} king is more than one.")]
+    TooManyKings { c: Color },
+    #[error("the side not to move is in check.")]
+    SideNotToMoveIsInCheck,
+    #[error("too many checkers. found {number} checkers.")]
+    TooManyCheckers { number: u32 },
+    #[error("{c:?} has two pawns on file {file}.")]
+    TwoPawnsOnFile { c: Color, file: char },
+    #[error(r##"the piece can't move anymore. found "{token}" on {square}."##)]
+    PieceCannotMove { token: String, square: String },
 }
@@ -1,4 +1,5 @@
 use crate::book::*;
+use crate::dfpn::*;
 #[cfg(feature = "kppt")]
 use crate::evaluate::kppt::*;
 #[cfg(feature = "material")]
@@ -49,6 +50,10 @@ struct Thread {
     ehash: *mut EvalHash,
     reductions: *mut Reductions,
     usi_options: UsiOptions,
+    entering_king_rule: EnteringKingRule, // Clone from usi_options for fast access.
+    max_moves_to_draw: i32,               // Clone from usi_options for fast access. 0 means no limit.
+    draw_values: [Value; Color::NUM],     // The values of draws for the side to move. They include Contempt.
+    mate_cache: MateCache,
     best_move_changes: Arc<AtomicU64>,
     best_move_changess: Vec<Arc<AtomicU64>>,
 
@@ -60,6 +65,7 @@ struct Thread {
     // following variables are used only main thread.
     previous_time_reduction: f64,
     calls_count: i32,
+    last_heartbeat_millis: i64, // The elapsed time of the last "info nodes ..." which is output regularly.
     stop_on_ponderhit: Arc<AtomicBool>,
     ponder: Arc<AtomicBool>,
     stop: Arc<AtomicBool>,
@@ -76,7 +82,9 @@ struct ThreadPoolBase {
 pub struct ThreadPool {
     thread_pool_base: Arc<Mutex<ThreadPoolBase>>,
     nodess: Vec<Arc<AtomicI64>>,
-    pub book: Option<Book>,
+    pub book: Option<AnyBook>,
+    pub book_moves_played: Vec<(String, Move)>, // The sfens and the book moves played in the current game. For book learning.
+    pondering_book_move: Option<(String, Move)>, // The book move of ponder. This is played by ponderhit.
     timeman: Arc<Mutex<TimeManagement>>,
     best_previous_score: Arc<Mutex<Value>>,
     iter_values: Arc<Mutex<[Value; 4]>>,
@@ -88,6 +96,8 @@ pub struct ThreadPool {
     pub hide_all_output: Arc<AtomicBool>,
     pub limits: LimitsType,
     pub last_best_root_move: Arc<Mutex<Option<RootMove>>>, // Not for usi engine. For debug or some tools.
+    pub last_pv_root_moves: Arc<Mutex<RootMoves>>,         // The first MultiPV root moves of the last search. For some tools.
+    mate_table: Arc<Mutex<MateTable>>,                     // For "go mate". It is allocated at the first "go mate".
     handle: Option<std::thread::JoinHandle<()>>,
 }
 
@@ -101,6 +111,7 @@ impl Thread {
         self.main_history.fill(0);
         self.low_ply_history.fill(0);
         self.capture_history.fill(0);
+        self.mate_cache.clear();
 
         self.continuation_history.iter_mut().for_each(|x| {
             x.iter_mut().for_each(|y| {
@@ -128,6 +139,7 @@ impl Thread {
             get_stack_mut(&mut stack, i64::from(i)).ply = i;
         }
         if self.is_main() {
+            self.last_heartbeat_millis = 0;
             let best_previous_score = *self.best_previous_score.lock().unwrap();
             if best_previous_score == Value::INFINITE {
                 for item in self.iter_values.lock().unwrap().iter_mut() {
@@ -293,17 +305,18 @@ impl Thread {
                 let best_move_instability = 1.073
                     + 1.0f64.max(2.25 - 9.9 / self.root_depth.0 as f64) * total_best_move_changes
                         / self.best_move_changess.len() as f64;
-                let (elapsed, optimum_millis) = {
+                let (elapsed, optimum_millis, minimum_millis) = {
                     let timeman = self.timeman.lock().unwrap();
-                    (timeman.elapsed(), timeman.optimum_millis())
+                    (timeman.elapsed(), timeman.optimum_millis(), timeman.minimum_millis())
                 };
                 let total_time = {
                     let total_time = (optimum_millis as f64 * falling_eval * reduction * best_move_instability) as i64;
-                    if self.root_moves.len() == 1 {
+                    let total_time = if self.root_moves.len() == 1 && !self.usi_options.get_bool(UsiOptions::USI_ANALYSE_MODE) {
                         std::cmp::min(500, total_time)
                     } else {
                         total_time
-                    }
+                    };
+                    std::cmp::max(minimum_millis, total_time)
                 };
                 if elapsed > total_time {
                     if self.ponder.load(Ordering::Relaxed) {
@@ -376,7 +389,15 @@ impl Thread {
         let mut beta = beta;
         if !root_node {
             // Step 2
-            match self.position.is_repetition() {
+            // The children of the root use the exact result of the whole game if the game is over.
+            let repetition = match get_stack(stack, 0).ply {
+                1 => match self.position.game_repetition() {
+                    Repetition::Not => self.position.is_repetition(),
+                    repetition => repetition,
+                },
+                _ => self.position.is_repetition(),
+            };
+            match repetition {
                 Repetition::Not => {
                     if self.stop.load(Ordering::Relaxed) || get_stack(stack, 0).ply >= MAX_PLY {
                         return if get_stack(stack, 0).ply >= MAX_PLY && !get_stack(stack, 0).in_check {
@@ -387,21 +408,24 @@ impl Thread {
                                 self.ehash,
                             )
                         } else {
-                            value_draw(self.nodes.load(Ordering::Relaxed))
+                            value_draw(self.draw_value(), self.nodes.load(Ordering::Relaxed))
                         };
                     }
+                    if let Some(value) = self.max_moves_value(stack) {
+                        return value;
+                    }
                 }
-                Repetition::Draw => return Value::DRAW,
+                Repetition::Draw => return self.draw_value(),
                 Repetition::Win => return Value::mate_in(get_stack(stack, 0).ply),
                 Repetition::Lose => return Value::mated_in(get_stack(stack, 0).ply),
                 Repetition::Superior => {
                     if get_stack(stack, 0).ply != 2 {
-                        return Value::MATE_IN_MAX_PLY;
+                        return self.superior_value();
                     }
                 }
                 Repetition::Inferior => {
                     if get_stack(stack, 0).ply != 2 {
-                        return Value::MATED_IN_MAX_PLY;
+                        return self.inferior_value();
                     }
                 }
             }
@@ -521,7 +545,23 @@ impl Thread {
         }
 
         // Step 5
-        if self.position.is_entering_king_win() {
+        if self.entering_king_rule == EnteringKingRule::TryRule {
+            if let Some(try_move) = self.position.try_rule_move() {
+                best_value = Value::mate_in(get_stack(stack, 0).ply);
+                get_stack_mut(stack, 0).static_eval = best_value; // is this necessary?
+                tte.save(
+                    key,
+                    value_to_tt(best_value, get_stack(stack, 0).ply),
+                    get_stack(stack, 0).tt_pv,
+                    Bound::EXACT,
+                    depth,
+                    Some(try_move),
+                    best_value,
+                    unsafe { (*self.tt).generation() },
+                );
+                return best_value;
+            }
+        } else if self.position.is_declaration_win(self.entering_king_rule) {
             best_value = Value::mate_in(get_stack(stack, 0).ply);
             if tt_move.is_none() || tt_move.non_zero_unwrap_unchecked() != Move::WIN {
                 get_stack_mut(stack, 0).static_eval = best_value; // is this necessary?
@@ -555,6 +595,22 @@ impl Thread {
                 );
                 return best_value;
             }
+            if !pv_node && depth <= Depth(3) {
+                if let Some(mate_move) = self.mate_move_in_3ply() {
+                    best_value = Value::mate_in(get_stack(stack, 0).ply + 2);
+                    tte.save(
+                        key,
+                        value_to_tt(best_value, get_stack(stack, 0).ply),
+                        get_stack(stack, 0).tt_pv,
+                        Bound::LOWER,
+                        depth,
+                        Some(mate_move),
+                        Value::NONE,
+                        unsafe { (*self.tt).generation() },
+                    );
+                    return best_value;
+                }
+            }
         }
 
         let pure_static_eval = if root_node {
@@ -582,7 +638,7 @@ impl Thread {
                     get_stack_mut(stack, 0).static_eval = eval;
                 }
                 if eval == Value::NONE {
-                    eval = value_draw(self.nodes.load(Ordering::Relaxed));
+                    eval = value_draw(Value::DRAW, self.nodes.load(Ordering::Relaxed));
                 }
                 if tt_value != Value::NONE
                     && if tt_value > eval {
@@ -849,6 +905,19 @@ impl Thread {
             move_count += 1;
             get_stack_mut(stack, 0).move_count = move_count;
 
+            if root_node
+                && self.is_main()
+                && !self.hide_all_output.load(Ordering::Relaxed)
+                && self.timeman.lock().unwrap().elapsed() > 3000
+            {
+                println!(
+                    "info depth {} currmove {} currmovenumber {}",
+                    depth.0,
+                    m.to_usi_string(),
+                    move_count + self.pv_idx as i32
+                );
+            }
+
             let mut extension = Depth::ZERO;
             let is_capture_or_pawn_promotion = m.is_capture_or_pawn_promotion(&self.position);
             let piece_moved_after_move = m.piece_moved_after_move();
@@ -1063,7 +1132,7 @@ impl Thread {
                     rm.score = value;
                     rm.sel_depth = self.sel_depth;
                     rm.pv.truncate(1);
-                    rm.extract_pv_from_tt(&mut self.position, self.tt);
+                    rm.extract_pv_from_tt(&mut self.position, self.tt, self.max_moves_to_draw);
                     if move_count > 1 {
                         self.best_move_changes.fetch_add(1, Ordering::Relaxed);
                     }
@@ -1167,6 +1236,47 @@ impl Thread {
 
         best_value
     }
+    #[inline]
+    fn draw_value(&self) -> Value {
+        self.draw_values[self.position.side_to_move().0 as usize]
+    }
+    // Superior and Inferior are the repetitions with more or fewer hand pieces. They are shifted by Contempt like the draws.
+    #[inline]
+    fn superior_value(&self) -> Value {
+        SUPERIOR_VALUE + (self.draw_value() - Value::DRAW)
+    }
+    #[inline]
+    fn inferior_value(&self) -> Value {
+        -SUPERIOR_VALUE + (self.draw_value() - Value::DRAW)
+    }
+    // Position::mate_move_in_3ply() with the cache. The result is shared by search and qsearch.
+    fn mate_move_in_3ply(&mut self) -> Option<Move> {
+        // The mate must be before the draw by MaxMovesToDraw.
+        if self.max_moves_to_draw != 0 && self.position.ply() + 2 > self.max_moves_to_draw {
+            return None;
+        }
+        let key = self.position.key();
+        if let Some(mate_move) = self.mate_cache.probe(key) {
+            return mate_move;
+        }
+        let mate_move = self.position.mate_move_in_3ply();
+        self.mate_cache.save(key, mate_move);
+        mate_move
+    }
+    // The value of the position beyond the maximum number of moves. It is a draw unless the side to move is mated.
+    fn max_moves_value(&self, stack: &[Stack]) -> Option<Value> {
+        if self.max_moves_to_draw == 0 || self.position.ply() <= self.max_moves_to_draw {
+            return None;
+        }
+        if get_stack(stack, 0).in_check {
+            let mut mlist = MoveList::new();
+            mlist.generate::<LegalType>(&self.position, 0);
+            if mlist.size == 0 {
+                return Some(Value::mated_in(get_stack(stack, 0).ply));
+            }
+        }
+        Some(self.draw_value())
+    }
     fn qsearch<NT: NodeTypeTrait>(&mut self, stack: &mut [Stack], alpha: Value, beta: Value, depth: Depth) -> Value {
         let pv_node = NT::NODE_TYPE == PV;
         let mut alpha = alpha;
@@ -1178,12 +1288,25 @@ impl Thread {
         get_stack_mut(stack, 0).in_check = self.position.in_check();
         let mut move_count = 0;
 
-        // We don't have to check repetition.
-        // Because qsearch use only capture-moves, promotion-moves, and evasion-moves.
-        // Their moves don't reach repetition positions.
+        // The quiet checks at the first ply of qsearch can reach repetition positions, so the positions in check
+        // at the second ply are checked. The other moves are capture-moves, promotion-moves and evasion-moves.
+        // We don't check repetition for them because it is rare and checking it is slow.
+        if get_stack(stack, 0).in_check && depth == Depth::QS_CHECKS - Depth::ONE_PLY {
+            match self.position.is_repetition() {
+                Repetition::Not => {}
+                Repetition::Draw => return self.draw_value(),
+                Repetition::Win => return Value::mate_in(get_stack(stack, 0).ply),
+                Repetition::Lose => return Value::mated_in(get_stack(stack, 0).ply),
+                Repetition::Superior => return self.superior_value(),
+                Repetition::Inferior => return self.inferior_value(),
+            }
+        }
         if get_stack_mut(stack, 0).ply >= MAX_PLY {
             return Value::DRAW;
         }
+        if let Some(value) = self.max_moves_value(stack) {
+            return value;
+        }
 
         debug_assert!(0 <= get_stack(stack, 0).ply && get_stack(stack, 0).ply < MAX_PLY);
 
@@ -1233,6 +1356,12 @@ impl Thread {
             if let Some(_mate_move) = self.position.mate_move_in_1ply() {
                 return Value::mate_in(get_stack(stack, 0).ply);
             }
+            // Mates by drops are often missed in qsearch, so we check them only at the first ply of qsearch.
+            if depth == Depth::QS_CHECKS && self.position.hand(self.position.side_to_move()).except_pawn_exist() {
+                if let Some(_mate_move) = self.mate_move_in_3ply() {
+                    return Value::mate_in(get_stack(stack, 0).ply + 2);
+                }
+            }
             if get_stack(stack, 0).tt_hit {
                 best_value = tte.eval();
                 get_stack_mut(stack, 0).static_eval = best_value;
@@ -1440,12 +1569,26 @@ impl Thread {
             None => 1024,
         };
 
+        let elapsed = self.limits.start_time.unwrap().elapsed();
+
+        // Output regularly even if the PV isn't updated.
+        let elapsed_millis = elapsed.as_millis() as i64 + 1; // "+ 1": avoid dividing by 0
+        if elapsed_millis - self.last_heartbeat_millis >= 1000 && !self.hide_all_output.load(Ordering::Relaxed) {
+            self.last_heartbeat_millis = elapsed_millis;
+            let nodes_searched = self.nodes_searched();
+            println!(
+                "info nodes {} nps {} hashfull {} time {}",
+                nodes_searched,
+                nodes_searched * 1000 / elapsed_millis,
+                unsafe { (*self.tt).hashfull() },
+                elapsed_millis
+            );
+        }
+
         if self.ponder.load(Ordering::Relaxed) {
             return;
         }
 
-        let elapsed = self.limits.start_time.unwrap().elapsed();
-
         if (self.limits.use_time_management()
             && (elapsed.as_millis() as i64 > self.timeman.lock().unwrap().maximum_millis() - 10
                 || self.stop_on_ponderhit.load(Ordering::Relaxed)))
@@ -1547,7 +1690,7 @@ impl Thread {
                 v = Value::ZERO;
             }
             let line = format!(
-                "info depth {depth} seldepth {seldepth} multipv {multipv} score {score} {bound}nodes {nodes} nps {nps} time {time} pv {pv}",
+                "info depth {depth} seldepth {seldepth} multipv {multipv} score {score} {bound}nodes {nodes} nps {nps} hashfull {hashfull} time {time} pv {pv}",
                 depth = d.0,
                 seldepth = rm.sel_depth,
                 multipv = i + 1,
@@ -1559,6 +1702,7 @@ impl Thread {
                 } else {""},
                 nodes = nodes_searched,
                 nps = nodes_searched * 1000 / elapsed_millis,
+                hashfull = unsafe { (*self.tt).hashfull() },
                 time = elapsed_millis,
                 pv = rm.pv.iter().map(|m| m.to_usi_string()).collect::<Vec<_>>().join(" ")
             );
@@ -1578,12 +1722,29 @@ impl Thread {
     }
 }
 
+// The value of Superior repetitions. It is below the mate range by the maximum Contempt (3000 centipawns),
+// so it isn't taken as a mate when it is shifted by Contempt.
+const SUPERIOR_VALUE: Value = Value(Value::MATE_IN_MAX_PLY.0 - 1 - 3000 * PAWN_VALUE / 100);
+
+// The values of draws for each side to move. "us" is the side to move at the root.
+fn draw_values(usi_options: &UsiOptions, us: Color) -> [Value; Color::NUM] {
+    // The option is centipawn.
+    let contempt = Value(usi_options.get_i64(UsiOptions::CONTEMPT) as i32 * PAWN_VALUE / 100);
+    let mut draw_values = [Value::DRAW - contempt; Color::NUM];
+    if !usi_options.get_bool(UsiOptions::CONTEMPT_BOTH_SIDES) {
+        draw_values[us.inverse().0 as usize] = Value::DRAW + contempt;
+    }
+    draw_values
+}
+
 impl ThreadPool {
     pub fn new() -> ThreadPool {
         ThreadPool {
             thread_pool_base: Arc::new(Mutex::new(ThreadPoolBase { threads: vec![] })),
             nodess: vec![],
             book: None,
+            book_moves_played: vec![],
+            pondering_book_move: None,
             timeman: Arc::new(Mutex::new(TimeManagement::new())),
             best_previous_score: Arc::new(Mutex::new(Value::INFINITE)),
             iter_values: Arc::new(Mutex::new([Value::ZERO; 4])),
@@ -1595,6 +1756,8 @@ impl ThreadPool {
             hide_all_output: Arc::new(AtomicBool::new(false)),
             limits: LimitsType::new(),
             last_best_root_move: Arc::new(Mutex::new(None)),
+            last_pv_root_moves: Arc::new(Mutex::new(RootMoves::new())),
+            mate_table: Arc::new(Mutex::new(MateTable::new(0))),
             handle: None,
         }
     }
@@ -1603,6 +1766,9 @@ impl ThreadPool {
             th.lock().unwrap().clear();
         }
         *self.last_best_root_move.lock().unwrap() = None;
+        self.last_pv_root_moves.lock().unwrap().clear();
+        self.book_moves_played.clear();
+        self.pondering_book_move = None;
 
         let thread_pool_base = self.thread_pool_base.lock().unwrap();
         let mut main_thread = thread_pool_base.threads[0].lock().unwrap();
@@ -1654,6 +1820,10 @@ impl ThreadPool {
                     ehash,
                     reductions,
                     usi_options: UsiOptions::new(),
+                    entering_king_rule: EnteringKingRule::CsaRule27,
+                    max_moves_to_draw: 0,
+                    draw_values: [Value::DRAW; Color::NUM],
+                    mate_cache: MateCache::new(),
                     best_move_changes: self.best_move_changess[i].clone(),
                     best_move_changess: self.best_move_changess.clone(),
                     nodes: self.nodess[i].clone(),
@@ -1662,6 +1832,7 @@ impl ThreadPool {
                     increase_depth: self.increase_depth.clone(),
                     previous_time_reduction: 1.0,
                     calls_count: 0,
+                    last_heartbeat_millis: 0,
                     stop_on_ponderhit: self.stop_on_ponderhit.clone(),
                     ponder: self.ponder.clone(),
                     stop: self.stop.clone(),
@@ -1690,15 +1861,33 @@ impl ThreadPool {
         if let Some(perft) = limits.perft {
             Perft::new(pos).go(perft);
             return;
-        } else if let Some(mate) = limits.mate {
-            Mate::new(pos).go(mate);
-            return;
         }
         self.wait_for_search_finished();
         self.stop.store(false, Ordering::Relaxed);
         self.stop_on_ponderhit.store(false, Ordering::Relaxed);
         self.ponder.store(ponder_mode, Ordering::Relaxed);
         self.hide_all_output.store(hide_all_output, Ordering::Relaxed);
+        if let Some(mate) = limits.mate {
+            // "go mate" runs on the search thread like the normal search, so "stop" can interrupt it.
+            let mut mate_searcher = Mate::new(pos);
+            let mate_hash = usi_options.get_i64(UsiOptions::MATE_HASH) as usize;
+            let mate_table_cloned = self.mate_table.clone();
+            let stop_cloned = self.stop.clone();
+            self.handle = Some(
+                std::thread::Builder::new()
+                    .stack_size(crate::stack_size::STACK_SIZE)
+                    .spawn(move || {
+                        let mut mate_table = mate_table_cloned.lock().unwrap();
+                        mate_table.resize(mate_hash);
+                        let s = mate_searcher.go(&mut mate_table, mate, &stop_cloned, hide_all_output);
+                        if !hide_all_output {
+                            println!("{}", s);
+                        }
+                    })
+                    .unwrap(),
+            );
+            return;
+        }
         self.timeman
             .lock()
             .unwrap()
@@ -1709,21 +1898,33 @@ impl ThreadPool {
             let mut mlist = MoveList::new();
             mlist.generate::<LegalType>(pos, 0);
             let mut root_moves = RootMoves::new();
-            let book_move = if usi_options.get_bool(UsiOptions::BOOK_ENABLE) {
+            let restricted = !limits.search_moves.is_empty() || !limits.ignore_moves.is_empty();
+            let book_move = if usi_options.get_bool(UsiOptions::BOOK_ENABLE) && !restricted {
                 match &self.book {
-                    Some(book) => book.probe(pos, &mut rand::thread_rng()),
+                    Some(book) => book.probe_with_settings(pos, &BookProbeSettings::new(usi_options), &mut rand::thread_rng()),
                     None => None,
                 }
             } else {
                 None
             };
+            self.pondering_book_move = None;
             match book_move {
-                Some(book_move) => {
+                Some((book_move, value)) => {
+                    if !hide_all_output {
+                        println!("info depth 0 score {} pv {}", value.to_usi(), book_move.to_usi_string());
+                    }
+                    if ponder_mode {
+                        self.pondering_book_move = Some((pos.to_sfen(), book_move));
+                    } else {
+                        self.book_moves_played.push((pos.to_sfen(), book_move));
+                    }
                     root_moves.push(RootMove::new(book_move));
                 }
                 None => {
                     for m in mlist.slice(0) {
-                        root_moves.push(RootMove::new(m.mv));
+                        if limits.is_searched_root_move(m.mv) {
+                            root_moves.push(RootMove::new(m.mv));
+                        }
                     }
                 }
             }
@@ -1739,23 +1940,44 @@ impl ThreadPool {
         let ponder_cloned = self.ponder.clone();
         let hide_all_output_cloned = self.hide_all_output.clone();
         let usi_options_cloned = usi_options.clone();
+        let entering_king_rule = EnteringKingRule::new_from_str(&usi_options.get_string(UsiOptions::ENTERING_KING_RULE)).unwrap();
+        let max_moves_to_draw = usi_options.get_i64(UsiOptions::MAX_MOVES_TO_DRAW) as i32;
+        let draw_values = draw_values(usi_options, pos.side_to_move());
         let last_best_root_move_cloned = self.last_best_root_move.clone();
+        let last_pv_root_moves_cloned = self.last_pv_root_moves.clone();
         self.handle = Some(
             std::thread::Builder::new()
                 .stack_size(crate::stack_size::STACK_SIZE)
                 .spawn(move || {
-                    if root_moves.is_empty() || pos.is_entering_king_win() {
+                    let winning_move = if root_moves.is_empty() {
+                        None
+                    } else if entering_king_rule == EnteringKingRule::TryRule {
+                        pos.try_rule_move()
+                    } else if pos.is_declaration_win(entering_king_rule) {
+                        Some(Move::WIN)
+                    } else {
+                        None
+                    };
+                    if root_moves.is_empty() || winning_move.is_some() {
                         while !stop_cloned.load(Ordering::Relaxed)
                             && (ponder_cloned.load(Ordering::Relaxed) || limits.infinite.is_some())
                         {
                             std::thread::sleep(std::time::Duration::from_millis(1));
                         }
-                        let m = if root_moves.is_empty() {
-                            *last_best_root_move_cloned.lock().unwrap() = Some(RootMove::new(Move::RESIGN));
-                            "resign"
-                        } else {
-                            *last_best_root_move_cloned.lock().unwrap() = Some(RootMove::new(Move::WIN));
-                            "win"
+                        last_pv_root_moves_cloned.lock().unwrap().clear();
+                        let m = match winning_move {
+                            None => {
+                                *last_best_root_move_cloned.lock().unwrap() = Some(RootMove::new(Move::RESIGN));
+                                "resign".to_string()
+                            }
+                            Some(Move::WIN) => {
+                                *last_best_root_move_cloned.lock().unwrap() = Some(RootMove::new(Move::WIN));
+                                "win".to_string()
+                            }
+                            Some(try_move) => {
+                                *last_best_root_move_cloned.lock().unwrap() = Some(RootMove::new(try_move));
+                                try_move.to_usi_string()
+                            }
                         };
                         if !hide_all_output_cloned.load(Ordering::Relaxed) {
                             println!("bestmove {}", m);
@@ -1788,6 +2010,9 @@ impl ThreadPool {
                             th.root_depth = Depth::ZERO;
                             th.root_moves = root_moves_cloned;
                             th.position = pos;
+                            th.entering_king_rule = entering_king_rule;
+                            th.max_moves_to_draw = max_moves_to_draw;
+                            th.draw_values = draw_values;
                             th.usi_options = usi_options_cloned;
                             th.timeman = timeman_cloned;
                             th.iterative_deepening_loop();
@@ -1884,10 +2109,17 @@ impl ThreadPool {
                         }
                     }
                     *last_best_root_move_cloned.lock().unwrap() = Some(best_thread.lock().unwrap().root_moves[0].clone());
+                    *last_pv_root_moves_cloned.lock().unwrap() = best_thread.lock().unwrap().root_moves[..multi_pv].to_vec();
                 })
                 .unwrap(),
         );
     }
+    pub fn ponderhit(&mut self) {
+        self.ponder.store(false, Ordering::Relaxed);
+        if let Some(played) = self.pondering_book_move.take() {
+            self.book_moves_played.push(played);
+        }
+    }
     pub fn wait_for_search_finished(&mut self) {
         if let Some(handle) = self.handle.take() {
             handle.join().unwrap();
@@ -1953,4 +2185,157 @@ mod tests {
             .join()
             .unwrap();
     }
+
+    #[test]
+    fn test_draw_values() {
+        let mut thread_pool = ThreadPool::new();
+        let mut tt = TranspositionTable::new();
+        #[cfg(feature = "kppt")]
+        let mut ehash = EvalHash::new();
+        let mut reductions = Reductions::new();
+        let mut is_ready = false;
+        let mut usi_options = UsiOptions::new();
+        let mut set = |usi_options: &mut UsiOptions, name: &str, value: &str| {
+            usi_options.set(
+                name,
+                value,
+                &mut thread_pool,
+                &mut tt,
+                #[cfg(feature = "kppt")]
+                &mut ehash,
+                &mut reductions,
+                &mut is_ready,
+            )
+        };
+        assert_eq!(draw_values(&usi_options, Color::BLACK), [Value::DRAW; Color::NUM]);
+        // 100 centipawns is PAWN_VALUE. The side to move at the root avoids draws.
+        set(&mut usi_options, UsiOptions::CONTEMPT, "100");
+        assert_eq!(
+            draw_values(&usi_options, Color::BLACK),
+            [Value(-PAWN_VALUE), Value(PAWN_VALUE)]
+        );
+        assert_eq!(
+            draw_values(&usi_options, Color::WHITE),
+            [Value(PAWN_VALUE), Value(-PAWN_VALUE)]
+        );
+        // Both sides avoid draws.
+        set(&mut usi_options, UsiOptions::CONTEMPT_BOTH_SIDES, "true");
+        assert_eq!(draw_values(&usi_options, Color::BLACK), [Value(-PAWN_VALUE); Color::NUM]);
+        assert_eq!(draw_values(&usi_options, Color::WHITE), [Value(-PAWN_VALUE); Color::NUM]);
+    }
+
+    #[test]
+    fn test_superior_and_inferior_values() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let mut thread_pool = ThreadPool::new();
+                let mut tt = TranspositionTable::new();
+                #[cfg(feature = "kppt")]
+                let mut ehash = EvalHash::new();
+                let mut reductions = Reductions::new();
+                thread_pool.set(
+                    1,
+                    &mut tt,
+                    #[cfg(feature = "kppt")]
+                    &mut ehash,
+                    &mut reductions,
+                );
+                let base = thread_pool.thread_pool_base.lock().unwrap();
+                let mut th = base.threads[0].lock().unwrap();
+                th.position = Position::new();
+                th.draw_values = [Value::DRAW; Color::NUM];
+                assert_eq!(th.superior_value(), SUPERIOR_VALUE);
+                assert_eq!(th.inferior_value(), -SUPERIOR_VALUE);
+                // Shifted by Contempt like the draws.
+                th.draw_values = [Value(-PAWN_VALUE), Value(PAWN_VALUE)];
+                assert_eq!(th.superior_value(), SUPERIOR_VALUE - Value(PAWN_VALUE));
+                assert_eq!(th.inferior_value(), -SUPERIOR_VALUE - Value(PAWN_VALUE));
+                // They don't reach the mate range with the maximum Contempt.
+                for contempt in [-3000, 3000] {
+                    th.draw_values = [Value(contempt * PAWN_VALUE / 100); Color::NUM];
+                    assert!(Value::KNOWN_WIN < th.superior_value() && th.superior_value() < Value::MATE_IN_MAX_PLY);
+                    assert!(Value::MATED_IN_MAX_PLY < th.inferior_value() && th.inferior_value() < -Value::KNOWN_WIN);
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_max_moves_to_draw() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let mut thread_pool = ThreadPool::new();
+                let mut tt = TranspositionTable::new();
+                #[cfg(feature = "kppt")]
+                let mut ehash = EvalHash::new();
+                let mut reductions = Reductions::new();
+                tt.resize(16, &mut thread_pool);
+                thread_pool.set(
+                    1,
+                    &mut tt,
+                    #[cfg(feature = "kppt")]
+                    &mut ehash,
+                    &mut reductions,
+                );
+                {
+                    let base = thread_pool.thread_pool_base.lock().unwrap();
+                    let mut th = base.threads[0].lock().unwrap();
+                    let mut stack = [Stack::new(); MAX_PLY as usize + 10];
+                    get_stack_mut(&mut stack, 0).ply = 3;
+                    th.draw_values = [Value(-10), Value(10)];
+                    th.position =
+                        Position::new_from_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 11").unwrap();
+                    th.max_moves_to_draw = 0; // No limit.
+                    assert_eq!(th.max_moves_value(&stack), None);
+                    th.max_moves_to_draw = 11;
+                    assert_eq!(th.max_moves_value(&stack), None);
+                    th.max_moves_to_draw = 10;
+                    assert_eq!(th.max_moves_value(&stack), Some(Value(-10)));
+                    // Mated is not a draw.
+                    th.position = Position::new_from_sfen("4k4/4G4/4P4/9/9/9/9/9/4K4 w - 11").unwrap();
+                    get_stack_mut(&mut stack, 0).in_check = true;
+                    assert_eq!(th.max_moves_value(&stack), Some(Value::mated_in(3)));
+                }
+
+                // The PV is cut at the draw by MaxMovesToDraw.
+                let mut pos = Position::new();
+                pos.reserve_states();
+                let mut moves = vec![];
+                for (i, usi_move) in ["7g7f", "3c3d", "2g2f"].iter().enumerate() {
+                    let m = Move::new_from_usi_str(usi_move, &pos).unwrap();
+                    if 0 < i {
+                        let generation = tt.generation();
+                        let (tte, _) = tt.probe(pos.key());
+                        tte.save(
+                            pos.key(),
+                            Value::ZERO,
+                            true,
+                            Bound::EXACT,
+                            Depth(1),
+                            Some(m),
+                            Value::ZERO,
+                            generation,
+                        );
+                    }
+                    let gives_check = pos.gives_check(m);
+                    pos.do_move(m, gives_check);
+                    moves.push(m);
+                }
+                for m in moves.iter().rev() {
+                    pos.undo_move(*m);
+                }
+                for (max_moves_to_draw, pv_len) in [(0, 3), (3, 3), (2, 2), (1, 1)] {
+                    let mut rm = RootMove::new(moves[0]);
+                    rm.extract_pv_from_tt(&mut pos, &mut tt, max_moves_to_draw);
+                    assert_eq!(rm.pv, moves[..pv_len]);
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
 }
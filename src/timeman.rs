@@ -7,6 +7,7 @@ pub struct TimeManagement {
     start_time: Option<std::time::Instant>,
     optimum_time_milli: std::time::Duration,
     maximum_time_milli: std::time::Duration,
+    minimum_time_milli: std::time::Duration,
 }
 
 impl TimeManagement {
@@ -15,32 +16,36 @@ impl TimeManagement {
             start_time: None,
             optimum_time_milli: std::time::Duration::from_millis(0),
             maximum_time_milli: std::time::Duration::from_millis(0),
+            minimum_time_milli: std::time::Duration::from_millis(0),
         }
     }
     pub fn init(&mut self, usi_optoins: &UsiOptions, limits: &mut LimitsType, us: Color, ply: i32) {
         self.start_time = limits.start_time;
-        let moves_to_go = 50;
+        let byoyomi = limits.byoyomi.map_or(0, |b| b.as_millis() as i64);
+        // With byoyomi, running out of the main time isn't fatal, so spend it over fewer moves.
+        let moves_to_go = if byoyomi > 0 { 30 } else { 50 };
         let move_overhead = 10;
         let slow_mover = usi_optoins.get_i64(UsiOptions::SLOW_MOVER);
+        let time = limits.time[us.0 as usize].as_millis() as i64;
         let time_left = std::cmp::max(
             1,
-            limits.time[us.0 as usize].as_millis() as i64 + limits.inc[us.0 as usize].as_millis() as i64 * (moves_to_go - 1)
-                - move_overhead * (2 + moves_to_go),
+            time + limits.inc[us.0 as usize].as_millis() as i64 * (moves_to_go - 1) - move_overhead * (2 + moves_to_go),
         );
         let time_left = time_left * slow_mover / 100;
 
-        let opt_scale = ((0.8 + ply as f64 / 128.0) / moves_to_go as f64)
-            .min(0.8 * limits.time[us.0 as usize].as_millis() as f64 / time_left as f64);
+        let opt_scale = ((0.8 + ply as f64 / 128.0) / moves_to_go as f64).min(0.8 * time as f64 / time_left as f64);
         let max_scale = 6.3f64.min(1.5 + 0.11 * moves_to_go as f64);
 
-        fn min(x: f64, y: f64) -> f64 {
-            x.min(y)
-        }
-        self.optimum_time_milli = std::time::Duration::from_millis((opt_scale * time_left as f64) as u64);
-        self.maximum_time_milli = std::time::Duration::from_millis(min(
-            0.8 * limits.time[us.0 as usize].as_millis() as f64 - move_overhead as f64,
-            max_scale * self.optimum_time_milli.as_millis() as f64,
-        ) as u64);
+        // The part of the main time we plan to spend on this move. Byoyomi is added on top of it,
+        // and when the main time is gone both become zero, which leaves pure byoyomi.
+        let optimum_from_time = opt_scale * time_left as f64;
+        let maximum_from_time = (0.8 * time as f64 - move_overhead as f64)
+            .min(max_scale * optimum_from_time)
+            .max(0.0);
+        self.optimum_time_milli = std::time::Duration::from_millis(optimum_from_time as u64 + byoyomi as u64);
+        self.maximum_time_milli = std::time::Duration::from_millis(maximum_from_time as u64 + byoyomi as u64);
+        // The byoyomi is lost if we don't use it, so never stop thinking before it runs out.
+        self.minimum_time_milli = std::time::Duration::from_millis(byoyomi as u64);
     }
     pub fn optimum_millis(&self) -> i64 {
         self.optimum_time_milli.as_millis() as i64
@@ -48,8 +53,76 @@ impl TimeManagement {
     pub fn maximum_millis(&self) -> i64 {
         self.maximum_time_milli.as_millis() as i64
     }
+    pub fn minimum_millis(&self) -> i64 {
+        self.minimum_time_milli.as_millis() as i64
+    }
     pub fn elapsed(&self) -> i64 {
         let duration = self.start_time.unwrap().elapsed();
         (duration.as_secs() * 1000 + u64::from(duration.subsec_millis())) as i64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(time: u64, byoyomi: Option<u64>) -> LimitsType {
+        let mut limits = LimitsType::new();
+        limits.start_time = Some(std::time::Instant::now());
+        limits.time = [std::time::Duration::from_millis(time); 2];
+        limits.byoyomi = byoyomi.map(std::time::Duration::from_millis);
+        limits
+    }
+
+    fn init(limits: &mut LimitsType) -> TimeManagement {
+        let usi_options = UsiOptions::new();
+        let mut timeman = TimeManagement::new();
+        timeman.init(&usi_options, limits, Color::BLACK, 1);
+        timeman
+    }
+
+    #[test]
+    fn test_use_time_management() {
+        assert!(limits(60000, None).use_time_management());
+        assert!(limits(60000, Some(10000)).use_time_management());
+        assert!(limits(0, Some(10000)).use_time_management());
+        let mut l = limits(60000, None);
+        l.movetime = Some(std::time::Duration::from_millis(10000));
+        assert!(!l.use_time_management());
+    }
+
+    #[test]
+    fn test_init_time_only() {
+        let timeman = init(&mut limits(60000, None));
+        assert!(0 < timeman.optimum_millis());
+        assert!(timeman.optimum_millis() <= timeman.maximum_millis());
+        assert!(timeman.maximum_millis() <= 60000 * 8 / 10);
+        assert_eq!(timeman.minimum_millis(), 0);
+    }
+
+    #[test]
+    fn test_init_byoyomi_only() {
+        let timeman = init(&mut limits(0, Some(10000)));
+        assert_eq!(timeman.optimum_millis(), 10000);
+        assert_eq!(timeman.maximum_millis(), 10000);
+        assert_eq!(timeman.minimum_millis(), 10000);
+    }
+
+    #[test]
+    fn test_init_time_and_byoyomi() {
+        let time_only = init(&mut limits(60000, None));
+        let timeman = init(&mut limits(60000, Some(10000)));
+        assert_eq!(timeman.minimum_millis(), 10000);
+        assert!(10000 < timeman.optimum_millis());
+        assert!(timeman.optimum_millis() <= timeman.maximum_millis());
+        assert!(timeman.maximum_millis() <= 60000 + 10000);
+        // The main time is spent faster than without byoyomi.
+        assert!(time_only.optimum_millis() < timeman.optimum_millis() - 10000);
+
+        // Less main time, less time to think.
+        let little_time = init(&mut limits(1000, Some(10000)));
+        assert!(10000 <= little_time.optimum_millis());
+        assert!(little_time.optimum_millis() < timeman.optimum_millis());
+        assert!(little_time.maximum_millis() <= 1000 + 10000);
+    }
+}
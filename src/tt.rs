@@ -184,6 +184,19 @@ impl TranspositionTable {
     pub fn generation(&self) -> u8 {
         self.generation8
     }
+    // The permill of the entries of the current search. This is estimated from the first 1000 entries.
+    pub fn hashfull(&self) -> usize {
+        let cluster_count = std::cmp::min(1000 / CLUSTER_SIZE, self.table.len());
+        if cluster_count == 0 {
+            return 0;
+        }
+        let count = self.table[..cluster_count]
+            .iter()
+            .flat_map(|cluster| cluster.entry.iter())
+            .filter(|entry| entry.depth8 != 0 && (entry.genbound8 & GENERATION_MASK as u8) == self.generation8)
+            .count();
+        count * 1000 / (cluster_count * CLUSTER_SIZE)
+    }
 }
 
 #[cfg(test)]
@@ -231,6 +244,46 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_hashfull() {
+        #[cfg(feature = "kppt")]
+        use crate::evaluate::kppt::*;
+        use crate::search::*;
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let mut thread_pool = ThreadPool::new();
+                let mut tt = TranspositionTable::new();
+                #[cfg(feature = "kppt")]
+                let mut ehash = EvalHash::new();
+                let mut reductions = Reductions::new();
+                thread_pool.set(
+                    1,
+                    &mut tt,
+                    #[cfg(feature = "kppt")]
+                    &mut ehash,
+                    &mut reductions,
+                );
+                tt.resize(1, &mut thread_pool);
+                assert_eq!(tt.hashfull(), 0);
+
+                let gen8 = tt.generation8;
+                for cluster in tt.table[..1000 / CLUSTER_SIZE / 2].iter_mut() {
+                    for entry in cluster.entry.iter_mut() {
+                        entry.save(Key(0), Value(0), false, Bound::EXACT, Depth(1), None, Value(0), gen8);
+                    }
+                }
+                assert_eq!(tt.hashfull(), 498);
+
+                // The entries of the previous search aren't counted.
+                tt.new_search();
+                assert_eq!(tt.hashfull(), 0);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
     #[test]
     fn test_probe() {
         #[cfg(feature = "kppt")]
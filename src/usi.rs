@@ -1,16 +1,19 @@
 use crate::book::*;
+use crate::csa::*;
 #[cfg(feature = "kppt")]
 use crate::evaluate::kppt::*;
 #[cfg(feature = "material")]
 use crate::evaluate::material::*;
 use crate::file_to_vec::*;
 use crate::huffman_code::*;
+use crate::jkf::*;
+use crate::kif::*;
 use crate::learn::*;
 use crate::movegen::*;
 use crate::movetypes::*;
 use crate::position::*;
 use crate::search::*;
-use crate::sfen::START_SFEN;
+use crate::sfen::*;
 use crate::thread::*;
 use crate::tt::*;
 use crate::types::*;
@@ -25,6 +28,14 @@ fn go(
     pos: &Position,
     args: &[&str],
 ) -> Result<()> {
+    let (limits, ponder_mode) = go_limits(usi_options, pos, args)?;
+    let hide_all_output = false;
+    thread_pool.start_thinking(pos, tt, limits, usi_options, ponder_mode, hide_all_output);
+    Ok(())
+}
+
+// The limits and the ponder mode of "go".
+fn go_limits(usi_options: &UsiOptions, pos: &Position, args: &[&str]) -> Result<(LimitsType, bool)> {
     let mut limits = LimitsType::new();
     limits.start_time = Some(std::time::Instant::now());
     let mut iter = args.iter();
@@ -56,11 +67,16 @@ fn go(
             "byoyomi" | "movetime" => {
                 let n = next_num(limit_type, &mut iter)?;
                 let byoyomi_margin = usi_options.get_i64(UsiOptions::BYOYOMI_MARGIN) as u64;
-                limits.movetime = if byoyomi_margin <= n {
+                let duration = if byoyomi_margin <= n {
                     Some(std::time::Duration::from_millis(n - byoyomi_margin))
                 } else {
                     Some(std::time::Duration::from_millis(0))
                 };
+                if limit_type == "byoyomi" {
+                    limits.byoyomi = duration;
+                } else {
+                    limits.movetime = duration;
+                }
             }
             "depth" => {
                 let n = next_num(limit_type, &mut iter)?;
@@ -86,12 +102,34 @@ fn go(
                 };
                 limits.mate = Some(n);
             }
+            // "ignoremoves" isn't a USI command. It is the opposite of "searchmoves".
+            "searchmoves" | "ignoremoves" => {
+                let mut moves = vec![];
+                while let Some(m) = iter.as_slice().first().and_then(|s| Move::new_from_usi_str(s, pos)) {
+                    moves.push(m);
+                    iter.next();
+                }
+                if moves.is_empty() {
+                    return Err(anyhow!("no legal move after {}.", limit_type));
+                }
+                if limit_type == "searchmoves" {
+                    limits.search_moves = moves;
+                } else {
+                    limits.ignore_moves = moves;
+                }
+            }
             invalid_token => return Err(anyhow!("invalid token: {}", invalid_token)),
         }
     }
-    let hide_all_output = false;
-    thread_pool.start_thinking(pos, tt, limits, usi_options, ponder_mode, hide_all_output);
-    Ok(())
+    if !limits.search_moves.is_empty() || !limits.ignore_moves.is_empty() {
+        let mut mlist = MoveList::new();
+        mlist.generate::<LegalType>(pos, 0);
+        // Searching no moves means resigning, so it is rejected.
+        if !mlist.slice(0).iter().any(|m| limits.is_searched_root_move(m.mv)) {
+            return Err(anyhow!("searchmoves and ignoremoves exclude all the legal moves."));
+        }
+    }
+    Ok((limits, ponder_mode))
 }
 
 fn isready(
@@ -115,7 +153,7 @@ fn isready(
         load_evaluate_files(&usi_options.get_string(UsiOptions::EVAL_DIR))?;
         if usi_options.get_bool(UsiOptions::BOOK_ENABLE) {
             let file_name = usi_options.get_filename(UsiOptions::BOOK_FILE);
-            let book = Book::from_file(&file_name).map_err(|e| anyhow!("{}: {}", e, file_name.to_string_lossy()))?;
+            let book = AnyBook::from_file(&file_name).map_err(|e| anyhow!("{}: {}", e, file_name.to_string_lossy()))?;
             thread_pool.book = Some(book);
         }
         tt.resize(usi_options.get_i64(UsiOptions::USI_HASH) as usize, thread_pool);
@@ -144,6 +182,42 @@ fn usi_new_game(thread_pool: &mut ThreadPool, _tt: &mut TranspositionTable) {
     //_tt.clear();
 }
 
+// Learn the book moves played in the game from the result.
+fn game_over(thread_pool: &mut ThreadPool, usi_options: &UsiOptions, args: &[&str]) {
+    fn game_over_impl(thread_pool: &mut ThreadPool, usi_options: &UsiOptions, args: &[&str]) -> Result<()> {
+        let win = match args.first() {
+            Some(&"win") => true,
+            Some(&"lose") => false,
+            Some(&"draw") => return Ok(()),
+            _ => return Err(anyhow!("expected: <win|lose|draw> found: {}", args.join(" "))),
+        };
+        let book_moves_played = std::mem::take(&mut thread_pool.book_moves_played);
+        let book = match &mut thread_pool.book {
+            Some(book) if !book_moves_played.is_empty() => book,
+            _ => return Ok(()),
+        };
+        let mut learned = 0;
+        for (sfen, m) in book_moves_played.iter() {
+            if book.learn(sfen, *m, win)? {
+                learned += 1;
+            }
+        }
+        if usi_options.get_bool(UsiOptions::BOOK_SAVE_ON_GAMEOVER) && learned > 0 {
+            let file_name = usi_options.get_filename(UsiOptions::BOOK_FILE);
+            book.to_file(&file_name)
+                .map_err(|e| anyhow!("{}: {}", e, file_name.to_string_lossy()))?;
+        }
+        println!("info string learned book moves {}", learned);
+        Ok(())
+    }
+
+    thread_pool.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+    thread_pool.wait_for_search_finished();
+    if let Err(e) = game_over_impl(thread_pool, usi_options, args) {
+        println!("info {}", e);
+    }
+}
+
 fn self_move(thread_pool: &mut ThreadPool, tt: &mut TranspositionTable, usi_options: &UsiOptions, pos: &Position) {
     let start_sfen = &pos.to_sfen();
     loop {
@@ -175,8 +249,8 @@ fn self_move(thread_pool: &mut ThreadPool, tt: &mut TranspositionTable, usi_opti
     }
 }
 
-fn position(pos: &mut Position, args: &[&str]) {
-    fn position_impl(pos: &mut Position, args: &[&str]) -> Result<()> {
+fn position(pos: &mut Position, args: &[&str], usi_options: &UsiOptions) {
+    fn position_impl(pos: &mut Position, args: &[&str], usi_options: &UsiOptions) -> Result<()> {
         if args.is_empty() {
             return Err(anyhow!(
                 r#"invalid position command. expected: "startpos" or "sfen". but found nothing"#,
@@ -193,13 +267,50 @@ fn position(pos: &mut Position, args: &[&str]) {
                 tmp_pos = Position::new_from_sfen_args(&args[1..]).map_err(|e| anyhow!("sfen error: {}", e))?;
                 &args[5..]
             }
+            // Not a USI command. "position handicap <name> moves ..."
+            // name is a handicap name of KIF (e.g. "角落ち") or JKF (e.g. "KA").
+            "handicap" => {
+                let name = args
+                    .get(1)
+                    .ok_or_else(|| anyhow!("invalid position command. expected: <name> after \"handicap\"."))?;
+                let sfen = handicap_sfen(name)
+                    .or_else(|| handicap_preset_to_name(name).and_then(handicap_sfen))
+                    .ok_or_else(|| anyhow!(r#"unknown handicap "{}""#, name))?;
+                tmp_pos = Position::new_from_sfen(sfen).unwrap();
+                &args[2..]
+            }
+            // Not a USI command. "position csa <file_path> moves ..."
+            "csa" => {
+                let path = args
+                    .get(1)
+                    .ok_or_else(|| anyhow!("invalid position command. expected: <file_path> after \"csa\"."))?;
+                let csa = std::fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", e, path))?;
+                tmp_pos = Position::new_from_csa_board(&csa).map_err(|e| anyhow!("csa error: {}", e))?;
+                &args[2..]
+            }
+            // Not a USI command. "position bod <file_path> moves ..."
+            "bod" => {
+                let path = args
+                    .get(1)
+                    .ok_or_else(|| anyhow!("invalid position command. expected: <file_path> after \"bod\"."))?;
+                let bod = std::fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", e, path))?;
+                tmp_pos = Position::new_from_bod(&bod).map_err(|e| anyhow!("bod error: {}", e))?;
+                &args[2..]
+            }
             _ => {
                 return Err(anyhow!(
-                    r#"invalid position command. expected: "startpos" or "sfen". found: "{}""#,
+                    r#"invalid position command. expected: "startpos", "sfen", "handicap", "csa" or "bod". found: "{}""#,
                     args[0]
                 ));
             }
         };
+        if let Err(e) = tmp_pos.validate() {
+            if usi_options.get_string(UsiOptions::ILLEGAL_POSITION) == "Warn" {
+                println!("info string illegal position. {}", e);
+            } else {
+                return Err(anyhow!("illegal position. {}", e));
+            }
+        }
         if args.is_empty() {
             *pos = tmp_pos;
             pos.reserve_states();
@@ -222,7 +333,7 @@ fn position(pos: &mut Position, args: &[&str]) {
         Ok(())
     }
 
-    if let Err(e) = position_impl(pos, args) {
+    if let Err(e) = position_impl(pos, args, usi_options) {
         println!("info {}", e);
     }
 }
@@ -440,6 +551,98 @@ fn read_hcp(args: &[&str]) {
     }
 }
 
+fn book_stats(args: &[&str]) {
+    fn book_stats_impl(args: &[&str]) -> Result<()> {
+        if args.len() != 1 {
+            return Err(anyhow!("expected: <input-path> found: {}", args.join(" ")));
+        }
+        let input_path = args[0];
+        let stats = if BinaryBook::is_binary_book_file(input_path).map_err(|e| anyhow!("{}: {}", e, input_path))? {
+            BinaryBook::from_file(input_path)
+                .map_err(|e| anyhow!("{}: {}", e, input_path))?
+                .stats()
+        } else {
+            Book::from_file_with_stats(input_path)
+                .map_err(|e| anyhow!("{}: {}", e, input_path))?
+                .1
+        };
+        println!("info string {}", stats);
+        Ok(())
+    }
+
+    if let Err(e) = book_stats_impl(args) {
+        println!("info {}", e);
+    }
+}
+
+// Convert the book to the format of <json|binary|yaneuraou>. The format of the input book is detected from the file.
+// The book made from the binary book has only the positions reached from the start position by the book moves.
+fn book_convert(args: &[&str]) {
+    fn book_convert_impl(args: &[&str]) -> Result<()> {
+        let usage = || {
+            anyhow!(
+                "expected: <json|binary|yaneuraou> <input-path> <output-path> found: {}",
+                args.join(" ")
+            )
+        };
+        if args.len() != 3 {
+            return Err(usage());
+        }
+        let format = args[0];
+        let input_path = args[1];
+        let output_path = args[2];
+        if !["json", "binary", "yaneuraou"].contains(&format) {
+            return Err(usage());
+        }
+        let book = match AnyBook::from_file(input_path).map_err(|e| anyhow!("{}: {}", e, input_path))? {
+            AnyBook::Binary(binary_book) if format == "binary" => {
+                binary_book
+                    .to_file(output_path)
+                    .map_err(|e| anyhow!("{}: {}", e, output_path))?;
+                println!("info string {}", binary_book.stats());
+                return Ok(());
+            }
+            AnyBook::Binary(binary_book) => {
+                let (book, unreached) = binary_book.to_book(&Position::new());
+                println!("info string unreached_moves {}", unreached);
+                book
+            }
+            AnyBook::Json(book) | AnyBook::YaneuraOuDb(book) => book,
+        };
+        match format {
+            "json" => book.to_file(output_path),
+            "binary" => BinaryBook::from_book(&book)?.to_file(output_path),
+            _ => book.to_yaneuraou_db_file(output_path),
+        }
+        .map_err(|e| anyhow!("{}: {}", e, output_path))?;
+        println!("info string {}", book.stats());
+        Ok(())
+    }
+
+    if let Err(e) = book_convert_impl(args) {
+        println!("info {}", e);
+    }
+}
+
+// Rewrite the book whose keys have game ply to the current format.
+fn migrate_book(args: &[&str]) {
+    fn migrate_book_impl(args: &[&str]) -> Result<()> {
+        if args.len() != 2 {
+            return Err(anyhow!("expected: <input-path> <output-path> found: {}", args.join(" ")));
+        }
+        let input_path = args[0];
+        let output_path = args[1];
+        let (book, stats) = Book::from_file_with_stats(input_path).map_err(|e| anyhow!("{}: {}", e, input_path))?;
+        book.to_file(output_path).map_err(|e| anyhow!("{}: {}", e, output_path))?;
+        println!("info string {}", stats);
+        Ok(())
+    }
+
+    if let Err(e) = migrate_book_impl(args) {
+        println!("info {}", e);
+    }
+}
+
 fn read_csa_dirs_and_output_sfen(dir_paths: &[&str]) {
     for dir_path in dir_paths.iter() {
         for path in std::fs::read_dir(dir_path).unwrap() {
@@ -454,74 +657,206 @@ fn read_csa_dirs_and_output_sfen(dir_paths: &[&str]) {
     }
 }
 
-fn csa_record_to_sfen(csa: &[u8]) -> Result<String> {
-    enum Phase {
-        InitialPositionAndOptionalInformation,
-        Moves,
-    }
-    let mut phase = Phase::InitialPositionAndOptionalInformation;
-    let mut _version = None;
-    let mut _player_black = None;
-    let mut _player_white = None;
-    let mut _event = None;
-    let mut _site = None;
-    let mut _start_time = None;
-    let mut _end_time = None;
-    let mut _time_limit = None;
-    let mut _opening = None;
-    let mut pos = Position::new();
-    let mut s = format!("sfen {} moves", START_SFEN);
-    for line in csa.split(|num_as_ascii| *num_as_ascii == b'\n') {
-        match phase {
-            Phase::InitialPositionAndOptionalInformation => {
-                if line.starts_with(b"'") {
-                    // line is a comment.
+// KI2 records need the extension ".ki2" or ".ki2u". The others are read as KIF.
+// Only UTF-8 files are read. The others (e.g. Shift_JIS ".kif" files) are skipped with a warning.
+fn read_kif_dirs_and_output_sfen(dir_paths: &[&str]) {
+    for dir_path in dir_paths.iter() {
+        for path in std::fs::read_dir(dir_path).unwrap() {
+            let path = path.unwrap().path();
+            let buf = match std::fs::read(&path).map(String::from_utf8) {
+                Ok(Ok(buf)) => buf,
+                Ok(Err(_)) => {
+                    println!("info skip {}. it isn't UTF-8.", path.display());
                     continue;
-                } else if line.starts_with(b"V") {
-                    _version = Some(line);
-                } else if line.starts_with(b"N+") {
-                    _player_black = Some(line);
-                } else if line.starts_with(b"N-") {
-                    _player_white = Some(line);
-                } else if line.starts_with(b"$EVENT:") {
-                    _event = Some(line);
-                } else if line.starts_with(b"$SITE:") {
-                    _site = Some(line);
-                } else if line.starts_with(b"$START_TIME:") {
-                    _start_time = Some(line);
-                } else if line.starts_with(b"$END_TIME:") {
-                    _end_time = Some(line);
-                } else if line.starts_with(b"$TIME_LIMIT:") {
-                    _time_limit = Some(line);
-                } else if line.starts_with(b"$OPENING:") {
-                    _opening = Some(line);
-                } else if line.starts_with(b"P") {
-                    // start position
-                    // todo: allow any position.
-                } else if line == b"+" || line == b"-" {
-                    phase = Phase::Moves;
                 }
-            }
-            Phase::Moves => {
-                if line.starts_with(b"'") {
-                    // line is a comment.
+                Err(e) => {
+                    println!("info skip {}. {}", path.display(), e);
                     continue;
-                } else if line.starts_with(b"%") {
-                    // game end.
-                } else if line.starts_with(b"+") || line.starts_with(b"-") {
-                    // black or white player's move
-                    let line = std::str::from_utf8(&line[1..])?;
-                    let m = Move::new_from_csa_str(line, &pos).context("illegal move")?;
-                    s += &format!(" {}", m.to_usi_string());
-                    let gives_check = pos.gives_check(m);
-                    pos.do_move(m, gives_check);
-                } else if line.starts_with(b"T") {
-                    // consumption time
                 }
+            };
+            let is_ki2 = matches!(path.extension().and_then(|ext| ext.to_str()), Some("ki2") | Some("ki2u"));
+            let record = if is_ki2 {
+                KifRecord::parse_ki2(&buf)
+            } else {
+                KifRecord::parse_kif(&buf)
+            };
+            if let Ok(record) = record {
+                println!("{}", record.to_sfen());
             }
         }
     }
-    Ok(s)
+}
+
+fn read_jkf_files_and_output_sfen(file_paths: &[&str]) {
+    for file_path in file_paths.iter() {
+        match Jkf::from_file(file_path).and_then(|jkf| jkf.to_sfen()) {
+            Ok(sfen) => println!("{}", sfen),
+            Err(e) => println!("info {:#}", e),
+        }
+    }
+}
+
+// The main line and the variations of each file.
+fn read_jkf_files_and_output_variations(file_paths: &[&str]) {
+    for file_path in file_paths.iter() {
+        match Jkf::from_file(file_path).and_then(|jkf| jkf.lines_to_sfen()) {
+            Ok(sfens) => sfens.iter().for_each(|sfen| println!("{}", sfen)),
+            Err(e) => println!("info {:#}", e),
+        }
+    }
+}
+
+// Write the game of the "position" command arguments as a JKF file.
+fn write_jkf(args: &[&str]) {
+    fn write_jkf_impl(args: &[&str]) -> Result<()> {
+        if args.len() < 2 {
+            return Err(anyhow!(
+                "expected: write_jkf <output_path> <startpos|sfen ...> [moves ...] found: {}",
+                args.join(" ")
+            ));
+        }
+        let (start, moves) = position_and_moves(&args[1..])?;
+        Jkf::new_from_moves(&start, &moves).to_file(args[0])
+    }
+    if let Err(e) = write_jkf_impl(args) {
+        println!("info {}", e);
+    }
+}
+
+// Write the game of the "position" command arguments as a KIF file.
+fn write_kif(args: &[&str]) {
+    fn write_kif_impl(args: &[&str]) -> Result<()> {
+        if args.len() < 2 {
+            return Err(anyhow!(
+                "expected: write_kif <output_path> <startpos|sfen ...> [moves ...] found: {}",
+                args.join(" ")
+            ));
+        }
+        let (start, moves) = position_and_moves(&args[1..])?;
+        std::fs::write(args[0], moves_to_kif_string(&start, &moves))?;
+        Ok(())
+    }
+    if let Err(e) = write_kif_impl(args) {
+        println!("info {}", e);
+    }
+}
+
+fn csa_record_to_sfen(csa: &[u8]) -> Result<String> {
+    Ok(csa_record_to_sfen_and_result(csa)?.0)
+}
+
+fn csa_record_to_sfen_and_result(csa: &[u8]) -> Result<(String, Option<GameResult>)> {
+    let record = CsaRecord::parse(csa)?;
+    Ok((record.to_sfen(), record.result))
+}
+
+// "startpos moves ..." or "sfen <sfen> moves ..." to the start position and the moves.
+fn position_and_moves(args: &[&str]) -> Result<(Position, Vec<Move>)> {
+    let (start, args) = match args.first() {
+        Some(&"startpos") => (Position::new(), &args[1..]),
+        Some(&"sfen") => (
+            Position::new_from_sfen_args(&args[1..]).map_err(|e| anyhow!("sfen error: {}", e))?,
+            &args[5..],
+        ),
+        _ => {
+            return Err(anyhow!(
+                r#"invalid position. expected: "startpos" or "sfen". found: "{}""#,
+                args.join(" ")
+            ))
+        }
+    };
+    let mut moves = vec![];
+    if args.is_empty() {
+        return Ok((start, moves));
+    }
+    if args[0] != "moves" {
+        return Err(anyhow!(r#"invalid position. expected: "moves". found: "{}""#, args[0]));
+    }
+    let mut pos = Position::new_from_position(&start, std::sync::Arc::new(std::sync::atomic::AtomicI64::new(0)));
+    for arg in &args[1..] {
+        let m =
+            Move::new_from_usi_str(arg, &pos).with_context(|| anyhow!("invalid move: {}, position: {}", arg, pos.to_sfen()))?;
+        let gives_check = pos.gives_check(m);
+        pos.do_move(m, gives_check);
+        moves.push(m);
+    }
+    Ok((start, moves))
+}
+
+// Input paths are directories of CSA records or files of "position startpos moves ..." lines.
+// A line may end with a CSA special move such as "%TORYO" to tell the result of the game.
+fn make_book(args: &[&str]) {
+    fn make_book_impl(args: &[&str]) -> Result<()> {
+        if args.len() < 4 {
+            return Err(anyhow!(
+                "expected: <output-path> <max-ply> <min-frequency> <input-path>... found: {}",
+                args.join(" ")
+            ));
+        }
+        let output_path = args[0];
+        let max_ply = args[1]
+            .parse::<i32>()
+            .with_context(|| anyhow!(r#"cannot parse "{}" as max-ply."#, args[1]))?;
+        let min_frequency = args[2]
+            .parse::<u64>()
+            .with_context(|| anyhow!(r#"cannot parse "{}" as min-frequency."#, args[2]))?;
+        let mut book_maker = BookMaker::new(max_ply);
+        let mut games = 0;
+        for input_path in &args[3..] {
+            if std::path::Path::new(input_path).is_dir() {
+                for path in std::fs::read_dir(input_path).map_err(|e| anyhow!("{}: {}", e, input_path))? {
+                    let path = path?.path();
+                    let buf = std::fs::read(&path).map_err(|e| anyhow!("{}: {}", e, path.display()))?;
+                    match CsaRecord::parse(&buf) {
+                        Ok(record) => {
+                            let moves = record.moves.iter().map(|m| m.mv).collect::<Vec<_>>();
+                            book_maker.add_game(&record.start_position(), &moves, record.result);
+                            games += 1;
+                        }
+                        Err(e) => println!("info {}: {}", e, path.display()),
+                    }
+                }
+            } else {
+                let input_file = std::fs::File::open(input_path).map_err(|e| anyhow!("{}: {}", e, input_path))?;
+                for line in std::io::BufReader::new(input_file).lines() {
+                    let line = line?;
+                    let mut args = line.split_whitespace().collect::<Vec<&str>>();
+                    if args.first() == Some(&"position") {
+                        args.remove(0);
+                    }
+                    if args.is_empty() {
+                        continue;
+                    }
+                    let special = if args.last().unwrap().starts_with('%') {
+                        args.pop()
+                    } else {
+                        None
+                    };
+                    match position_and_moves(&args) {
+                        Ok((start, moves)) => {
+                            let side_to_move = if moves.len() % 2 == 0 {
+                                start.side_to_move()
+                            } else {
+                                start.side_to_move().inverse()
+                            };
+                            let result = special.and_then(|special| csa_special_move_to_game_result(special, side_to_move));
+                            book_maker.add_game(&start, &moves, result);
+                            games += 1;
+                        }
+                        Err(e) => println!("info {}", e),
+                    }
+                }
+            }
+        }
+        let book = book_maker.into_book(min_frequency);
+        book.to_file(output_path).map_err(|e| anyhow!("{}: {}", e, output_path))?;
+        println!("info string games {} {}", games, book.stats());
+        Ok(())
+    }
+
+    if let Err(e) = make_book_impl(args) {
+        println!("info {}", e);
+    }
 }
 
 pub fn cmd_loop() {
@@ -562,7 +897,8 @@ pub fn cmd_loop() {
 
         match token {
             // Required commands as USI protocol.
-            "gameover" | "quit" | "stop" => {
+            "gameover" => game_over(&mut thread_pool, &usi_options, &args[1..]),
+            "quit" | "stop" => {
                 thread_pool.stop.store(true, std::sync::atomic::Ordering::Relaxed);
             }
             "go" => {
@@ -584,10 +920,8 @@ pub fn cmd_loop() {
                     &mut ehash,
                 );
             }
-            "ponderhit" => {
-                thread_pool.ponder.store(false, std::sync::atomic::Ordering::Relaxed);
-            }
-            "position" => position(&mut pos, &args[1..]),
+            "ponderhit" => thread_pool.ponderhit(),
+            "position" => position(&mut pos, &args[1..], &usi_options),
             "setoption" => setoption(
                 &args[1..],
                 &mut usi_options,
@@ -607,8 +941,22 @@ pub fn cmd_loop() {
             }
             "usinewgame" => usi_new_game(&mut thread_pool, &mut tt),
             // Not required commands as USI protocol.
+            "analyse_jkf" => {
+                if is_ready {
+                    if let Err(e) = analyse_jkf(&args[1..]) {
+                        println!("info {}", e);
+                    }
+                } else {
+                    println!(r#"info error. "isready" command is needed in advance."#);
+                }
+            }
             "bench_movegen" => bench_movegen(&pos),
-            "d" => pos.print(),
+            "book_convert" => book_convert(&args[1..]),
+            "book_stats" => book_stats(&args[1..]),
+            "d" => match args.get(1) {
+                Some(&"bod") => print!("{}", pos.to_bod_string()),
+                _ => pos.print(),
+            },
             "eval" => {
                 if is_ready {
                     let mut stack = vec![Stack::new(); CURRENT_STACK_INDEX + 1];
@@ -629,11 +977,27 @@ pub fn cmd_loop() {
             "key" => println!("{}", pos.key().0),
             "legal_moves" => legal_moves(&pos),
             "legal_all_moves" => legal_all_moves(&pos),
+            "make_book" => make_book(&args[1..]),
+            "migrate_book" => migrate_book(&args[1..]),
             "self_move" => self_move(&mut thread_pool, &mut tt, &usi_options, &pos),
             "read_csa_dirs_and_output_sfen" => read_csa_dirs_and_output_sfen(&args[1..]),
+            "read_jkf_files_and_output_sfen" => read_jkf_files_and_output_sfen(&args[1..]),
+            "read_jkf_files_and_output_variations" => read_jkf_files_and_output_variations(&args[1..]),
+            "read_kif_dirs_and_output_sfen" => read_kif_dirs_and_output_sfen(&args[1..]),
             "read_hcp" => read_hcp(&args[1..]),
             "read_sfen_and_output_hcp" => read_sfen_and_output_hcp(&args[1..]),
+            "think_book" => {
+                if is_ready {
+                    if let Err(e) = think_book(&args[1..]) {
+                        println!("info {}", e);
+                    }
+                } else {
+                    println!(r#"info error. "isready" command is needed in advance."#);
+                }
+            }
             "wait" => thread_pool.wait_for_search_finished(),
+            "write_jkf" => write_jkf(&args[1..]),
+            "write_kif" => write_kif(&args[1..]),
             "write_eval" => {
                 if is_ready {
                     #[cfg(feature = "kppt")]
@@ -655,6 +1019,7 @@ pub fn cmd_loop() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sfen::START_SFEN;
 
     #[test]
     fn test_csa_record_to_sfne() {
@@ -670,4 +1035,166 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_csa_record_to_sfen_and_result() {
+        let buf = std::fs::read("test/example.csa").unwrap();
+        let (_, result) = csa_record_to_sfen_and_result(&buf).unwrap();
+        assert_eq!(result, None); // %CHUDAN
+
+        let csa = "V2.2\nPI\n+\n+2726FU\n-3334FU\n%TORYO\n";
+        let (sfen, result) = csa_record_to_sfen_and_result(csa.as_bytes()).unwrap();
+        assert_eq!(
+            sfen,
+            "sfen lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1 moves 2g2f 3c3d"
+        );
+        assert_eq!(result, Some(GameResult::WhiteWin));
+
+        let csa = "V2.2\nPI\n+\n+2726FU\n%KACHI,T1\n";
+        let (_, result) = csa_record_to_sfen_and_result(csa.as_bytes()).unwrap();
+        assert_eq!(result, Some(GameResult::WhiteWin));
+
+        let csa = "V2.2\nPI\n+\n%SENNICHITE\n";
+        let (_, result) = csa_record_to_sfen_and_result(csa.as_bytes()).unwrap();
+        assert_eq!(result, Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn test_position_and_moves() {
+        let (start, moves) = position_and_moves(&["startpos", "moves", "2g2f", "3c3d"]).unwrap();
+        assert_eq!(start.to_sfen(), START_SFEN);
+        assert_eq!(
+            moves.iter().map(|m| m.to_usi_string()).collect::<Vec<_>>(),
+            vec!["2g2f", "3c3d"]
+        );
+        assert!(position_and_moves(&["startpos", "moves", "2g2f", "2g2f"]).is_err());
+        assert!(position_and_moves(&["startpos", "2g2f"]).is_err());
+    }
+
+    #[test]
+    fn test_go_limits() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let usi_options = UsiOptions::new();
+                let pos = Position::new();
+                let ms = std::time::Duration::from_millis;
+                let mv = |s| Move::new_from_usi_str(s, &pos).unwrap();
+
+                // Time_Margin and Byoyomi_Margin are 500 by default.
+                let args = ["btime", "60000", "wtime", "300", "byoyomi", "10000", "ponder"];
+                let (limits, ponder_mode) = go_limits(&usi_options, &pos, &args).unwrap();
+                assert_eq!(limits.time, [ms(59500), ms(0)]);
+                assert_eq!(limits.byoyomi, Some(ms(9500)));
+                assert!(ponder_mode);
+
+                let (limits, ponder_mode) = go_limits(&usi_options, &pos, &["depth", "5", "mate", "infinite"]).unwrap();
+                assert_eq!(limits.depth, Some(5));
+                assert_eq!(limits.mate, Some(0));
+                assert!(!ponder_mode);
+
+                let args = ["searchmoves", "7g7f", "2g2f", "ignoremoves", "2g2f", "depth", "3"];
+                let (limits, _) = go_limits(&usi_options, &pos, &args).unwrap();
+                assert_eq!(limits.search_moves, vec![mv("7g7f"), mv("2g2f")]);
+                assert_eq!(limits.ignore_moves, vec![mv("2g2f")]);
+                assert_eq!(limits.depth, Some(3));
+                assert!(limits.is_searched_root_move(mv("7g7f")));
+                assert!(!limits.is_searched_root_move(mv("2g2f")));
+                assert!(!limits.is_searched_root_move(mv("5g5f")));
+
+                let (limits, _) = go_limits(&usi_options, &pos, &["ignoremoves", "7g7f"]).unwrap();
+                assert!(!limits.is_searched_root_move(mv("7g7f")));
+                assert!(limits.is_searched_root_move(mv("2g2f")));
+
+                assert!(go_limits(&usi_options, &pos, &["depth"]).is_err());
+                assert!(go_limits(&usi_options, &pos, &["depth", "x"]).is_err());
+                assert!(go_limits(&usi_options, &pos, &["searchmoves", "7g7e"]).is_err());
+                assert!(go_limits(&usi_options, &pos, &["unknown"]).is_err());
+                // No moves are left to search.
+                assert!(go_limits(&usi_options, &pos, &["searchmoves", "7g7f", "ignoremoves", "7g7f"]).is_err());
+                let pos = Position::new_from_sfen("4k4/9/9/9/9/9/9/9/K8 b - 1").unwrap();
+                assert!(go_limits(&usi_options, &pos, &["ignoremoves", "9i9h", "9i8h", "9i8i"]).is_err());
+                assert!(go_limits(&usi_options, &pos, &["ignoremoves", "9i9h", "9i8h"]).is_ok());
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_position_illegal_position() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let mut thread_pool = ThreadPool::new();
+                let mut tt = TranspositionTable::new();
+                #[cfg(feature = "kppt")]
+                let mut ehash = EvalHash::new();
+                let mut reductions = Reductions::new();
+                let mut is_ready = false;
+                let mut usi_options = UsiOptions::new();
+                let mut pos = Position::new();
+                // Two pawns on a file.
+                let args = ["sfen", "4k4/9/9/9/9/4P4/4P4/9/4K4", "b", "-", "1"];
+                position(&mut pos, &args, &usi_options);
+                assert_eq!(pos.to_sfen(), START_SFEN);
+
+                setoption(
+                    &["name", UsiOptions::ILLEGAL_POSITION, "value", "Warn"],
+                    &mut usi_options,
+                    &mut thread_pool,
+                    &mut tt,
+                    #[cfg(feature = "kppt")]
+                    &mut ehash,
+                    &mut reductions,
+                    &mut is_ready,
+                );
+                position(&mut pos, &args, &usi_options);
+                assert_eq!(pos.to_sfen(), args[1..].join(" "));
+
+                // The side not to move is in check. The engine can't handle it even with "Warn".
+                position(&mut pos, &["sfen", "4k4/4G4/9/9/9/9/9/9/4K4", "b", "-", "1"], &usi_options);
+                assert_eq!(pos.to_sfen(), args[1..].join(" "));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_write_kif() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                let path = std::env::temp_dir().join(format!("apery_test_write_kif_{}.kif", std::process::id()));
+                write_kif(&[path.to_str().unwrap(), "startpos", "moves", "7g7f", "3c3d", "8h2b+", "3a2b"]);
+                let kif = std::fs::read_to_string(&path).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                assert_eq!(
+                    KifRecord::parse_kif(&kif).unwrap().to_sfen(),
+                    format!("sfen {} moves 7g7f 3c3d 8h2b+ 3a2b", START_SFEN)
+                );
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_read_kif_dirs_and_output_sfen() {
+        std::thread::Builder::new()
+            .stack_size(crate::stack_size::STACK_SIZE)
+            .spawn(|| {
+                // A Shift_JIS file is skipped without panic.
+                let dir = std::env::temp_dir().join(format!("apery_test_read_kif_dirs_{}", std::process::id()));
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(dir.join("sjis.kif"), b"\x8e\xe8\x90\x94\x81\x81\x8e\x77\x8e\xe8\n").unwrap();
+                std::fs::write(dir.join("utf8.kif"), "手数----指手---------消費時間--\n   1 ２六歩(27)\n").unwrap();
+                read_kif_dirs_and_output_sfen(&[dir.to_str().unwrap()]);
+                std::fs::remove_dir_all(&dir).unwrap();
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
 }
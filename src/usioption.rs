@@ -26,6 +26,11 @@ enum UsiOptionValue {
         default: bool,
         current: bool,
     },
+    Combo {
+        default: &'static str,
+        current: String,
+        vars: &'static [&'static str],
+    },
     Button,
 }
 
@@ -58,6 +63,14 @@ impl UsiOptionValue {
             current: default,
         }
     }
+    fn combo(default: &'static str, vars: &'static [&'static str]) -> UsiOptionValue {
+        debug_assert!(vars.contains(&default));
+        UsiOptionValue::Combo {
+            default,
+            current: default.to_string(),
+            vars,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -68,15 +81,27 @@ pub struct UsiOptions {
 impl UsiOptions {
     pub const BOOK_ENABLE: &'static str = "Book_Enable";
     pub const BOOK_FILE: &'static str = "Book_File";
+    pub const BOOK_MIN_GAMES: &'static str = "Book_Min_Games";
+    pub const BOOK_NARROW_MARGIN: &'static str = "Book_Narrow_Margin";
+    pub const BOOK_POLICY: &'static str = "Book_Policy";
+    pub const BOOK_SAVE_ON_GAMEOVER: &'static str = "Book_Save_On_Gameover";
     pub const BYOYOMI_MARGIN: &'static str = "Byoyomi_Margin";
     const CLEAR_HASH: &'static str = "Clear_Hash";
+    pub const CONTEMPT: &'static str = "Contempt";
+    pub const CONTEMPT_BOTH_SIDES: &'static str = "Contempt_Both_Sides";
+    pub const ENTERING_KING_RULE: &'static str = "EnteringKingRule";
     pub const EVAL_DIR: &'static str = "Eval_Dir";
     #[cfg(feature = "kppt")]
     pub const EVAL_HASH: &'static str = "Eval_Hash";
+    pub const ILLEGAL_POSITION: &'static str = "Illegal_Position";
+    pub const MATE_HASH: &'static str = "Mate_Hash";
+    pub const MAX_MOVES_TO_DRAW: &'static str = "MaxMovesToDraw";
     pub const MULTI_PV: &'static str = "MultiPV";
+    pub const NARROW_BOOK: &'static str = "Narrow_Book";
     pub const SLOW_MOVER: &'static str = "Slow_Mover";
     pub const THREADS: &'static str = "Threads";
     pub const TIME_MARGIN: &'static str = "Time_Margin";
+    pub const USI_ANALYSE_MODE: &'static str = "USI_AnalyseMode";
     pub const USI_HASH: &'static str = "USI_Hash";
     pub const USI_PONDER: &'static str = "USI_Ponder";
 
@@ -86,15 +111,40 @@ impl UsiOptions {
         // The following are all options.
         options.insert(Self::BOOK_ENABLE, UsiOptionValue::check(false));
         options.insert(Self::BOOK_FILE, UsiOptionValue::filename("book/20191216/book.json"));
+        options.insert(Self::BOOK_MIN_GAMES, UsiOptionValue::spin(0, 0, i64::MAX));
+        options.insert(Self::BOOK_NARROW_MARGIN, UsiOptionValue::spin(100, 0, 100_000));
+        options.insert(
+            Self::BOOK_POLICY,
+            UsiOptionValue::combo("WinRate", &["BestValue", "WinCount", "WinRate", "Uniform"]),
+        );
+        options.insert(Self::BOOK_SAVE_ON_GAMEOVER, UsiOptionValue::check(false));
         options.insert(Self::BYOYOMI_MARGIN, UsiOptionValue::spin(500, 0, i64::MAX));
         options.insert(Self::CLEAR_HASH, UsiOptionValue::Button);
+        // centipawn. The positive value avoids draws.
+        options.insert(Self::CONTEMPT, UsiOptionValue::spin(0, -3000, 3000));
+        // For analysis. Both sides avoid draws by Contempt, not only the side to move at the root.
+        options.insert(Self::CONTEMPT_BOTH_SIDES, UsiOptionValue::check(false));
+        options.insert(
+            Self::ENTERING_KING_RULE,
+            UsiOptionValue::combo("CSARule27", &["None", "CSARule24", "CSARule27", "TryRule"]),
+        );
         options.insert(Self::EVAL_DIR, UsiOptionValue::string("eval/20190617"));
         #[cfg(feature = "kppt")]
         options.insert(Self::EVAL_HASH, UsiOptionValue::spin(256, 1, 1024 * 1024));
+        // "Warn" accepts the positions against the rules (e.g. two pawns on a file) after printing the reason.
+        // The positions which the engine can't handle (e.g. the side not to move is in check) are always rejected.
+        options.insert(Self::ILLEGAL_POSITION, UsiOptionValue::combo("Reject", &["Reject", "Warn"]));
+        // The table size of "go mate" in MB. It is separated from USI_Hash and kept between "go mate" commands.
+        options.insert(Self::MATE_HASH, UsiOptionValue::spin(64, 1, 1024 * 1024));
+        // The game is a draw when this number of moves are played. 0 means no limit.
+        options.insert(Self::MAX_MOVES_TO_DRAW, UsiOptionValue::spin(0, 0, 100_000));
         options.insert(Self::MULTI_PV, UsiOptionValue::spin(1, 1, 500));
+        options.insert(Self::NARROW_BOOK, UsiOptionValue::check(false));
         options.insert(Self::SLOW_MOVER, UsiOptionValue::spin(100, 10, 1000));
         options.insert(Self::THREADS, UsiOptionValue::spin(1, 1, 8192));
         options.insert(Self::TIME_MARGIN, UsiOptionValue::spin(500, 0, i64::MAX));
+        // Analysis doesn't save time. e.g. It searches until the limit even if there is only one legal move.
+        options.insert(Self::USI_ANALYSE_MODE, UsiOptionValue::check(false));
         const MAX_HASH_MB: usize = 0x200_0000;
         options.insert(Self::USI_HASH, UsiOptionValue::spin(256, 1, MAX_HASH_MB as i64));
         options.insert(Self::USI_PONDER, UsiOptionValue::check(true));
@@ -178,6 +228,13 @@ impl UsiOptions {
                     *is_ready = false;
                 }
             }
+            Some(UsiOptionValue::Combo { current, vars, .. }) => {
+                if vars.contains(&value) {
+                    *current = value.to_string();
+                } else {
+                    println!("Error: illegal option value: {}", value);
+                }
+            }
             Some(UsiOptionValue::Button) => println!(r#"Error: The option "{}" is button type. You can't set value to it."#, key),
         }
     }
@@ -198,6 +255,10 @@ impl UsiOptions {
                 UsiOptionValue::Check { default, .. } => {
                     format!("option name {} type check default {}", key, default)
                 }
+                UsiOptionValue::Combo { default, vars, .. } => {
+                    let vars = vars.iter().map(|var| format!(" var {}", var)).collect::<String>();
+                    format!("option name {} type combo default {}{}", key, default, vars)
+                }
                 UsiOptionValue::Button => format!("option name {} type button", key),
             })
             .collect::<Vec<_>>();
@@ -213,7 +274,7 @@ impl UsiOptions {
     #[allow(dead_code)]
     pub fn get_string(&self, key: &str) -> String {
         match self.v.get(key) {
-            Some(UsiOptionValue::String { current, .. }) => current.clone(),
+            Some(UsiOptionValue::String { current, .. }) | Some(UsiOptionValue::Combo { current, .. }) => current.clone(),
             _ => panic!("Error: illegal option name: {}", key),
         }
     }